@@ -0,0 +1,170 @@
+//! Colored terminal rendering of diffs.
+
+use std::ops::Range;
+
+use crate::{push_visible, shown, FuDiff, Granularity};
+
+/// The escape sequences used by `FuDiff::render_color`.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ColorMode {
+    /// No escape sequences. Visible whitespace still applies.
+    None,
+    /// The 16 basic ANSI colors, with changed text within a line shown in
+    /// reverse video.
+    #[default]
+    Basic,
+    /// The 256-color palette, with changed text within a line shown on a
+    /// darker background.
+    Ansi256,
+}
+
+/// Options controlling colored rendering.
+#[derive(Debug, Clone)]
+pub struct ColorOptions {
+    /// The escape sequences to emit.
+    pub mode: ColorMode,
+    /// Show spaces as `·` and tabs as `→`.
+    pub whitespace: bool,
+    /// Highlight the changed parts of replaced lines, compared in units of
+    /// this granularity. `None` disables highlighting.
+    pub highlight: Option<Granularity>,
+}
+
+impl Default for ColorOptions {
+    fn default() -> Self {
+        ColorOptions {
+            mode: ColorMode::default(),
+            whitespace: false,
+            highlight: Some(Granularity::Word),
+        }
+    }
+}
+
+/// The escape sequences for each kind of line in one color mode.
+struct Palette {
+    reset: &'static str,
+    header: &'static str,
+    context: &'static str,
+    deletion: &'static str,
+    addition: &'static str,
+    deleted: &'static str,
+    added: &'static str,
+}
+
+impl Palette {
+    fn new(mode: ColorMode) -> Self {
+        match mode {
+            ColorMode::None => Palette {
+                reset: "",
+                header: "",
+                context: "",
+                deletion: "",
+                addition: "",
+                deleted: "",
+                added: "",
+            },
+            ColorMode::Basic => Palette {
+                reset: "\x1b[0m",
+                header: "\x1b[36m",
+                context: "\x1b[2m",
+                deletion: "\x1b[31m",
+                addition: "\x1b[32m",
+                deleted: "\x1b[7m",
+                added: "\x1b[7m",
+            },
+            ColorMode::Ansi256 => Palette {
+                reset: "\x1b[0m",
+                header: "\x1b[38;5;75m",
+                context: "\x1b[38;5;245m",
+                deletion: "\x1b[38;5;203m",
+                addition: "\x1b[38;5;114m",
+                deleted: "\x1b[48;5;52m",
+                added: "\x1b[48;5;22m",
+            },
+        }
+    }
+}
+
+impl FuDiff {
+    /// Renders this diff like `render`, colored with ANSI escape sequences:
+    /// deletions in red, additions in green, and context dimmed. Where a hunk
+    /// replaces lines, the changed text within each pair of lines is
+    /// highlighted. Every line, including the last, ends with a newline.
+    pub fn render_color(&self, opts: &ColorOptions) -> String {
+        let p = Palette::new(opts.mode);
+        let mut r = Renderer {
+            out: String::new(),
+            whitespace: opts.whitespace,
+            reset: p.reset,
+        };
+        for hunk in &self.hunks {
+            let spans = opts
+                .highlight
+                .map(|g| hunk.intraline(g))
+                .unwrap_or_default();
+            r.out.push_str(&format!("{}@@ @@{}\n", p.header, p.reset));
+            for l in &hunk.context_before {
+                r.line(p.context, ' ', shown(l), &[], "");
+            }
+            for (i, l) in hunk.deletions.iter().enumerate() {
+                let changed = spans.get(i).map_or(&[][..], |s| &s.old);
+                r.line(p.deletion, '-', l, changed, p.deleted);
+            }
+            for (i, l) in hunk.additions.iter().enumerate() {
+                let changed = spans.get(i).map_or(&[][..], |s| &s.new);
+                r.line(p.addition, '+', l, changed, p.added);
+            }
+            for l in &hunk.context_after {
+                r.line(p.context, ' ', shown(l), &[], "");
+            }
+        }
+        r.out
+    }
+}
+
+/// Accumulates colored output.
+struct Renderer {
+    out: String,
+    whitespace: bool,
+    reset: &'static str,
+}
+
+impl Renderer {
+    /// Writes one line in `style`, with the byte ranges in `changed` also in
+    /// `highlight`.
+    fn line(
+        &mut self,
+        style: &str,
+        prefix: char,
+        text: &str,
+        changed: &[Range<usize>],
+        highlight: &str,
+    ) {
+        self.out.push_str(style);
+        self.out.push(prefix);
+        let mut at = 0;
+        for range in changed {
+            self.text(&text[at..range.start]);
+            self.out.push_str(highlight);
+            self.text(&text[range.clone()]);
+            self.out.push_str(self.reset);
+            self.out.push_str(style);
+            at = range.end;
+        }
+        self.text(&text[at..]);
+        self.out.push_str(self.reset);
+        self.out.push('\n');
+    }
+
+    /// Writes `text`, with control characters escaped, and whitespace made
+    /// visible if requested.
+    fn text(&mut self, text: &str) {
+        for c in text.chars() {
+            match c {
+                ' ' if self.whitespace => self.out.push('·'),
+                '\t' if self.whitespace => self.out.push('→'),
+                c => push_visible(&mut self.out, c),
+            }
+        }
+    }
+}
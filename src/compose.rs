@@ -0,0 +1,551 @@
+//! Composing two sequential diffs into one.
+
+use crate::lcs::unique_diff;
+use crate::{Error, FuDiff, Hunk, Result};
+
+/// A region of the intermediate text touched by the first diff: its lines
+/// before the first diff, and after, each flagged with whether it was changed.
+/// Lines deleted without replacement leave no line to flag, so the points
+/// they were deleted at, as offsets into the new lines, are kept as `cuts`.
+/// Once a hunk of the second diff is applied, later ones are only looked for
+/// from `done`, where its deletions ended.
+struct Fragment {
+    old: Vec<String>,
+    new: Vec<(String, bool)>,
+    cuts: Vec<usize>,
+    done: Option<usize>,
+}
+
+impl Fragment {
+    fn from_hunk(hunk: &Hunk) -> Self {
+        let unchanged = |l: &String| (l.clone(), false);
+        Fragment {
+            old: hunk
+                .context_before
+                .iter()
+                .chain(&hunk.deletions)
+                .chain(&hunk.context_after)
+                .cloned()
+                .collect(),
+            new: hunk
+                .context_before
+                .iter()
+                .map(unchanged)
+                .chain(hunk.additions.iter().map(|l| (l.clone(), true)))
+                .chain(hunk.context_after.iter().map(unchanged))
+                .collect(),
+            cuts: if hunk.additions.is_empty() && !hunk.deletions.is_empty() {
+                vec![hunk.context_before.len()]
+            } else {
+                Vec::new()
+            },
+            done: None,
+        }
+    }
+
+    /// Returns every offset, relative to the start of this fragment's new
+    /// lines, at which `hunk` overlaps the fragment consistently and touches
+    /// its changes: the overlap holds a line changed by either diff, the hunk
+    /// inserts strictly inside the fragment, or the hunk spans a point where
+    /// the fragment deleted lines. Offsets before `done` are passed over.
+    fn placements(&self, hunk: &Hunk) -> Vec<isize> {
+        self.matches(hunk)
+            .into_iter()
+            .filter_map(|(k, touches)| {
+                (touches && self.done.is_none_or(|d| k >= d as isize)).then_some(k)
+            })
+            .collect()
+    }
+
+    /// Whether `hunk` lies wholly within this fragment's new lines at an
+    /// offset where it touches none of its changes.
+    fn holds_untouched(&self, hunk: &Hunk) -> bool {
+        let p =
+            (hunk.context_before.len() + hunk.deletions.len() + hunk.context_after.len()) as isize;
+        self.matches(hunk)
+            .into_iter()
+            .any(|(k, touches)| !touches && k >= 0 && k + p <= self.new.len() as isize)
+    }
+
+    /// Returns every offset at which `hunk` overlaps the fragment
+    /// consistently, with whether it touches the fragment's changes there.
+    fn matches(&self, hunk: &Hunk) -> Vec<(isize, bool)> {
+        let old: Vec<&String> = hunk
+            .context_before
+            .iter()
+            .chain(&hunk.deletions)
+            .chain(&hunk.context_after)
+            .collect();
+        let deleted = hunk.context_before.len()..hunk.context_before.len() + hunk.deletions.len();
+        let (p, n) = (old.len() as isize, self.new.len() as isize);
+        (1 - p..n)
+            .filter_map(|k| {
+                let overlap = k.max(0)..(k + p).min(n);
+                let fits = overlap
+                    .clone()
+                    .all(|i| self.new[i as usize].0 == *old[(i - k) as usize]);
+                let touches = overlap
+                    .clone()
+                    .any(|i| self.new[i as usize].1 || deleted.contains(&((i - k) as usize)))
+                    || (hunk.deletions.is_empty() && {
+                        let at = k + hunk.context_before.len() as isize;
+                        0 < at && at < n
+                    })
+                    || self
+                        .cuts
+                        .iter()
+                        .any(|&c| k < c as isize && (c as isize) < k + p);
+                (!overlap.is_empty() && fits).then_some((k, touches))
+            })
+            .collect()
+    }
+
+    /// Absorbs `next`, which follows this fragment after `gap` unchanged
+    /// lines.
+    fn absorb(&mut self, gap: &[&String], next: Fragment) {
+        let offset = self.new.len() + gap.len();
+        self.cuts.extend(next.cuts.iter().map(|c| c + offset));
+        if let Some(done) = next.done {
+            self.done = Some(offset + done);
+        }
+        self.old.extend(gap.iter().map(|l| l.to_string()));
+        self.old.extend(next.old);
+        self.new.extend(gap.iter().map(|l| (l.to_string(), false)));
+        self.new.extend(next.new);
+    }
+
+    /// Absorbs `next`, whose first `shared` lines are also this fragment's
+    /// last. Returns `None`, leaving this fragment as it was, unless those
+    /// lines are unchanged context in both.
+    fn absorb_shared(&mut self, shared: usize, mut next: Fragment) -> Option<()> {
+        // Whether `f`'s lines from `new` and `old` on are `shared` lines of
+        // unchanged context.
+        let context = |f: &Fragment, new: usize, old: usize| {
+            let (n, o) = (&f.new[new..new + shared], &f.old[old..old + shared]);
+            n.iter().zip(o).all(|((n, changed), o)| n == o && !changed)
+                && !f.cuts.iter().any(|&c| new < c && c < new + shared)
+        };
+        let n = self.new.len().checked_sub(shared)?;
+        let o = self.old.len().checked_sub(shared)?;
+        if shared > next.new.len().min(next.old.len())
+            || !context(self, n, o)
+            || !context(&next, 0, 0)
+            || self.old[o..] != next.old[..shared]
+        {
+            return None;
+        }
+        self.cuts.extend(next.cuts.iter().map(|c| n + c));
+        if let Some(done) = next.done {
+            self.done = Some(n + done);
+        }
+        self.old.extend(next.old.drain(shared..));
+        self.new.extend(next.new.drain(shared..));
+        Some(())
+    }
+
+    /// Applies `hunk` at offset `k` of this fragment's new lines, extending
+    /// the fragment with the hunk's lines that lie beyond it.
+    fn apply(&mut self, hunk: &Hunk, k: isize) {
+        let old: Vec<&String> = hunk
+            .context_before
+            .iter()
+            .chain(&hunk.deletions)
+            .chain(&hunk.context_after)
+            .collect();
+        let mut k = k;
+        if k < 0 {
+            let before = &old[..(-k) as usize];
+            for c in &mut self.cuts {
+                *c += before.len();
+            }
+            self.done = self.done.map(|d| d + before.len());
+            self.old.splice(0..0, before.iter().map(|l| l.to_string()));
+            self.new
+                .splice(0..0, before.iter().map(|l| (l.to_string(), false)));
+            k = 0;
+        }
+        let k = k as usize;
+        let beyond = (k + old.len()).saturating_sub(self.new.len());
+        let after = &old[old.len() - beyond..];
+        self.old.extend(after.iter().map(|l| l.to_string()));
+        self.new
+            .extend(after.iter().map(|l| (l.to_string(), false)));
+
+        let cb = hunk.context_before.len();
+        let ca = hunk.context_after.len();
+        let end = k + old.len();
+        let replacement: Vec<(String, bool)> = self.new[k..k + cb]
+            .iter()
+            .cloned()
+            .chain(hunk.additions.iter().map(|l| (l.clone(), true)))
+            .chain(self.new[end - ca..end].iter().cloned())
+            .collect();
+        self.new.splice(k..end, replacement);
+
+        // Cuts inside the replaced lines are covered by the hunk's change.
+        let (start, added) = (k + cb, hunk.additions.len());
+        let delta = (cb + added + ca) as isize - old.len() as isize;
+        self.cuts.retain(|&c| c <= start || c >= end - ca);
+        for c in &mut self.cuts {
+            if *c >= end - ca && *c > start {
+                *c = (*c as isize + delta) as usize;
+            }
+        }
+        if added == 0 && !hunk.deletions.is_empty() {
+            self.cuts.push(start);
+        }
+        self.done = Some(start + added);
+    }
+
+    /// Converts this fragment back into a hunk, with the unchanged lines at
+    /// either end as context. Returns `None` if the fragment changes nothing.
+    fn into_hunk(self) -> Option<Hunk> {
+        let new: Vec<String> = self.new.iter().map(|(l, _)| l.clone()).collect();
+        if self.old == new {
+            return None;
+        }
+        let unchanged = |(o, (n, changed)): (&String, &(String, bool))| o == n && !changed;
+        let prefix = self
+            .old
+            .iter()
+            .zip(&self.new)
+            .take_while(|&p| unchanged(p))
+            .count();
+        let suffix = self.old[prefix..]
+            .iter()
+            .rev()
+            .zip(self.new[prefix..].iter().rev())
+            .take_while(|&p| unchanged(p))
+            .count();
+        Some(Hunk {
+            context_before: self.old[..prefix].to_vec(),
+            deletions: self.old[prefix..self.old.len() - suffix].to_vec(),
+            additions: new[prefix..new.len() - suffix].to_vec(),
+            context_after: self.old[self.old.len() - suffix..].to_vec(),
+        })
+    }
+}
+
+/// Whether two consecutive fragments may share lines of the original text
+/// that their composed hunks change: some lines ending `prev` also start
+/// `next`, and the context of the hunks on either side, `after` and
+/// `before` lines, doesn't cover them.
+fn may_share_changed(prev: &[String], after: usize, next: &[String], before: usize) -> bool {
+    (1..=prev.len().min(next.len()))
+        .any(|k| prev[prev.len() - k..] == next[..k] && (after < k || before < k))
+}
+
+/// The lines that place `hunk`: its context before, or without any, its
+/// deletions and context after.
+fn needle(hunk: &Hunk) -> Vec<&String> {
+    if hunk.context_before.is_empty() {
+        hunk.deletions.iter().chain(&hunk.context_after).collect()
+    } else {
+        hunk.context_before.iter().collect()
+    }
+}
+
+/// Whether `needle` matches `lines`, or runs off either end of them with
+/// its overlap matching, at any offset other than `own`.
+fn occurs(needle: &[&String], lines: &[String], own: Option<isize>) -> bool {
+    let (p, n) = (needle.len() as isize, lines.len() as isize);
+    p > 0
+        && (1 - p..n).any(|k| {
+            Some(k) != own
+                && (k.max(0)..(k + p).min(n))
+                    .all(|i| lines[i as usize] == *needle[(i - k) as usize])
+        })
+}
+
+/// Whether a diff of `hunks` removes its input's trailing newline: its last
+/// hunk only deletes lines, with no context after them.
+fn trims_newline(hunks: &[Hunk]) -> bool {
+    hunks.last().is_some_and(|h| {
+        !h.deletions.is_empty() && h.additions.is_empty() && h.context_after.is_empty()
+    })
+}
+
+/// Items of the composed diff, in order.
+enum Item {
+    /// Index into the fragments.
+    Fragment(usize),
+    /// A hunk of the second diff that touches none of the first's changes.
+    Hunk(usize),
+}
+
+impl FuDiff {
+    /// Composes `first`, taking A to B, with `second`, taking B to C, into a
+    /// single diff taking A to C, without the text of A.
+    ///
+    /// Each hunk of `second` that touches lines changed by `first` is folded
+    /// into the hunks of `first` it touches; the others are carried over as
+    /// they are. Fails if a hunk of `second` could be folded in at several
+    /// places, if the order of the composed hunks can't be told without A, if
+    /// two composed hunks may change the same lines of A, if a composed hunk
+    /// may match lines of A that the first diff changed, or if the composed
+    /// diff can't drop or keep the trailing newline as the two diffs do.
+    /// `compose_with` takes A and always succeeds if both diffs apply.
+    pub fn compose(first: &FuDiff, second: &FuDiff) -> Result<FuDiff> {
+        let mut fragments: Vec<Option<Fragment>> = Vec::new();
+        // Each fragment's index, once absorbed into an earlier fragment, is
+        // redirected to that fragment.
+        let mut owner: Vec<usize> = Vec::new();
+        for (index, hunk) in first.hunks.iter().enumerate() {
+            let fragment = Fragment::from_hunk(hunk);
+            // A hunk is placed at the first match of its lines after the
+            // previous hunk's deletions, so if they match within the context
+            // after them, the two hunks share those lines.
+            let needle = needle(hunk);
+            let shared = index.checked_sub(1).and_then(|prev| {
+                let merged = fragments[owner[prev]].as_ref().unwrap();
+                let from = merged.old.len() - first.hunks[prev].context_after.len();
+                (from..merged.old.len())
+                    .find(|&i| {
+                        merged.old[i..].len() >= needle.len()
+                            && needle.iter().zip(&merged.old[i..]).all(|(n, o)| *n == o)
+                    })
+                    .map(|i| (owner[prev], merged.old.len() - i))
+            });
+            match shared {
+                Some((f, shared)) => {
+                    fragments[f]
+                        .as_mut()
+                        .unwrap()
+                        .absorb_shared(shared, fragment)
+                        .ok_or_else(|| Error::Apply {
+                            user: "Cannot compose diffs".to_string(),
+                            details: format!(
+                                "Hunk {} of the first diff changes lines of the one before it",
+                                index + 1
+                            ),
+                        })?;
+                    fragments.push(None);
+                    owner.push(f);
+                }
+                None => {
+                    fragments.push(Some(fragment));
+                    owner.push(index);
+                }
+            }
+        }
+        let mut anchors: Vec<Option<usize>> = Vec::new();
+
+        for (index, hunk) in second.hunks.iter().enumerate() {
+            let placed: Vec<(usize, Vec<isize>)> = fragments
+                .iter()
+                .enumerate()
+                .filter_map(|(f, frag)| Some((f, frag.as_ref()?.placements(hunk))))
+                .filter(|(_, ks)| !ks.is_empty())
+                .collect();
+            let ambiguous = || Error::AmbiguousMatch {
+                user: "Cannot compose diffs".to_string(),
+                details: format!(
+                    "Hunk {} of the second diff matches the first diff's changes at several places",
+                    index + 1
+                ),
+            };
+            if placed.is_empty() {
+                if fragments
+                    .iter()
+                    .flatten()
+                    .any(|frag| !frag.matches(hunk).is_empty())
+                {
+                    return Err(ambiguous());
+                }
+                anchors.push(None);
+                continue;
+            }
+            // The hunk may also lie where it touches no changes.
+            if placed.iter().any(|(_, ks)| ks.len() > 1)
+                || fragments
+                    .iter()
+                    .flatten()
+                    .any(|frag| frag.holds_untouched(hunk))
+            {
+                return Err(ambiguous());
+            }
+
+            // The hunk spans consecutive fragments, with the unchanged lines
+            // between them taken from the hunk.
+            let old: Vec<&String> = hunk
+                .context_before
+                .iter()
+                .chain(&hunk.deletions)
+                .chain(&hunk.context_after)
+                .collect();
+            let (f, k) = (placed[0].0, placed[0].1[0]);
+            let lens: Vec<isize> = placed
+                .iter()
+                .map(|(g, _)| fragments[*g].as_ref().unwrap().new.len() as isize)
+                .collect();
+            let mut merged = fragments[f].take().unwrap();
+            for (i, pair) in placed.windows(2).enumerate() {
+                let ((prev, kp), (next, kn)) =
+                    ((pair[0].0, pair[0].1[0]), (pair[1].0, pair[1].1[0]));
+                // The gap starts where the previous fragment ends, measured
+                // from the start of the hunk.
+                let start = lens[i] - kp;
+                let gap = kp - kn - lens[i];
+                let misfit = || Error::Apply {
+                    user: "Cannot compose diffs".to_string(),
+                    details: format!(
+                        "Hunk {} of the second diff does not fit the first diff's changes",
+                        index + 1
+                    ),
+                };
+                if fragments[prev + 1..next].iter().any(|x| x.is_some()) {
+                    return Err(misfit());
+                }
+                let fragment = fragments[next].take().unwrap();
+                if gap < 0 {
+                    // The fragments share context lines.
+                    merged
+                        .absorb_shared((-gap) as usize, fragment)
+                        .ok_or_else(misfit)?;
+                } else {
+                    let gap_lines = &old[start as usize..(start + gap) as usize];
+                    merged.absorb(gap_lines, fragment);
+                }
+                for o in owner.iter_mut().filter(|o| **o == next) {
+                    *o = f;
+                }
+            }
+            merged.apply(hunk, k);
+            fragments[f] = Some(merged);
+            anchors.push(Some(f));
+        }
+
+        // Interleave the fragments with the carried-over hunks. A carried-over
+        // hunk can only be placed if no untouched fragment competes with it
+        // for the same stretch of text.
+        let live: Vec<usize> = (0..fragments.len())
+            .filter(|&f| fragments[f].is_some())
+            .collect();
+        let mut items = Vec::new();
+        let mut next = 0;
+        let mut pending = Vec::new();
+        let unordered = || Error::AmbiguousMatch {
+            user: "Cannot compose diffs".to_string(),
+            details: "The order of the composed hunks is unknown without the original text"
+                .to_string(),
+        };
+        for (index, anchor) in anchors.iter().enumerate() {
+            let Some(f) = anchor.map(|f| owner[f]) else {
+                pending.push(index);
+                continue;
+            };
+            let at = live.iter().position(|&l| l == f).unwrap();
+            if at < next {
+                if !pending.is_empty() || at + 1 < next {
+                    return Err(unordered());
+                }
+                continue;
+            }
+            if !pending.is_empty() && at > next {
+                return Err(unordered());
+            }
+            items.extend(pending.drain(..).map(Item::Hunk));
+            items.extend(live[next..=at].iter().map(|&f| Item::Fragment(f)));
+            next = at + 1;
+        }
+        if !pending.is_empty() && next < live.len() {
+            return Err(unordered());
+        }
+        items.extend(pending.drain(..).map(Item::Hunk));
+        items.extend(live[next..].iter().map(|&f| Item::Fragment(f)));
+
+        // The old lines of the previous fragment, and the context after its
+        // composed hunk.
+        let mut prev: Option<(Vec<String>, usize)> = None;
+        let mut hunks = Vec::new();
+        // The lines of the original text known from each composed fragment,
+        // with the index of its hunk.
+        let mut known: Vec<(usize, Vec<String>)> = Vec::new();
+        let count = items.len();
+        for (i, item) in items.into_iter().enumerate() {
+            match item {
+                Item::Fragment(f) => {
+                    let fragment = fragments[f].take().unwrap();
+                    let old = fragment.old.clone();
+                    let Some(hunk) = fragment.into_hunk() else {
+                        // Without the changes undone here, the hunks after
+                        // them lose the lines that placed them.
+                        if i + 1 < count {
+                            return Err(Error::AmbiguousMatch {
+                                user: "Cannot compose diffs".to_string(),
+                                details: "The second diff undoes a change the later hunks \
+                                          are placed after"
+                                    .to_string(),
+                            });
+                        }
+                        prev = None;
+                        continue;
+                    };
+                    if let Some((prev_old, after)) = &prev {
+                        if may_share_changed(prev_old, *after, &old, hunk.context_before.len()) {
+                            return Err(Error::AmbiguousMatch {
+                                user: "Cannot compose diffs".to_string(),
+                                details: format!(
+                                    "Composed hunks {} and {} may change the same lines of the \
+                                     original text",
+                                    hunks.len(),
+                                    hunks.len() + 1
+                                ),
+                            });
+                        }
+                    }
+                    known.push((hunks.len(), old.clone()));
+                    prev = Some((old, hunk.context_after.len()));
+                    hunks.push(hunk);
+                }
+                Item::Hunk(h) => {
+                    prev = None;
+                    hunks.push(second.hunks[h].clone());
+                }
+            }
+        }
+
+        // The first diff's changes bring back lines the second diff's context
+        // was unique without, so a hunk may be placed by lines it also finds
+        // among them.
+        for (index, hunk) in hunks.iter().enumerate() {
+            let needle = needle(hunk);
+            let found = known.iter().any(|(owner, old)| {
+                let own = (*owner == index).then_some(0);
+                occurs(&needle, old, own)
+            });
+            if found {
+                return Err(Error::AmbiguousMatch {
+                    user: "Cannot compose diffs".to_string(),
+                    details: format!(
+                        "Composed hunk {} may match several places of the original text",
+                        index + 1
+                    ),
+                });
+            }
+        }
+
+        // A last hunk that only deletes lines drops the trailing newline,
+        // which the composed diff must then do too.
+        if (trims_newline(&first.hunks) || trims_newline(&second.hunks)) != trims_newline(&hunks) {
+            return Err(Error::AmbiguousMatch {
+                user: "Cannot compose diffs".to_string(),
+                details: "The composed diff cannot keep the trailing newline as the diffs do"
+                    .to_string(),
+            });
+        }
+        Ok(FuDiff { hunks })
+    }
+
+    /// Composes `first`, taking `original` to B, with `second`, taking B to
+    /// C, into a single diff taking `original` to C. The hunks are rebuilt
+    /// from `original` and C, with context widened until each matches only
+    /// one place in `original`.
+    pub fn compose_with(first: &FuDiff, second: &FuDiff, original: &str) -> Result<FuDiff> {
+        let intermediate = first.patch(original)?;
+        let result = second.patch(&intermediate)?;
+        let old: Vec<&str> = original.lines().collect();
+        let new: Vec<&str> = result.lines().collect();
+        unique_diff(&old, &new)
+    }
+}
@@ -0,0 +1,117 @@
+//! Conflicts between changes to the same text.
+
+use std::ops::Range;
+
+use crate::locate::Location;
+use crate::{FuDiff, PatchOptions, Result};
+
+/// A place where a hunk's changes collide with other changes to the same
+/// base. Line ranges are zero-based indices into the base.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Conflict {
+    /// Index of the hunk within the diff.
+    pub hunk: usize,
+    /// Index of the other diff's hunk, if the other changes come from a diff.
+    pub other: Option<usize>,
+    /// The lines the hunk deletes. Empty for an insertion, at the point of
+    /// insertion. For a hunk that only has context, the lines of its
+    /// context.
+    pub ours: Range<usize>,
+    /// The lines changed by the other side. Empty for an insertion, at the
+    /// point of insertion. For a hunk that only has context, the lines of
+    /// its context.
+    pub theirs: Range<usize>,
+    /// A human-readable description of the conflict.
+    pub message: String,
+}
+
+/// Whether a change to `theirs` collides with a change to `ours`. Changes may
+/// touch at their edges, but an insertion strictly inside the other side's
+/// deleted lines collides with it.
+pub(crate) fn collides(ours: &Range<usize>, theirs: &Range<usize>) -> bool {
+    let inside = |point: usize, r: &Range<usize>| r.start < point && point < r.end;
+    match (ours.is_empty(), theirs.is_empty()) {
+        (false, false) => ours.start < theirs.end && theirs.start < ours.end,
+        (false, true) => inside(theirs.start, ours),
+        (true, false) => inside(ours.start, theirs),
+        (true, true) => false,
+    }
+}
+
+/// Formats a range of zero-based lines as one-based line numbers.
+pub(crate) fn describe(lines: &Range<usize>) -> String {
+    if lines.is_empty() {
+        format!("the insertion before line {}", lines.start + 1)
+    } else if lines.len() == 1 {
+        format!("line {}", lines.start + 1)
+    } else {
+        format!("lines {}-{}", lines.start + 1, lines.end)
+    }
+}
+
+impl FuDiff {
+    /// Reports the hunks of this diff and `other` that can't both be applied
+    /// to `base`: pairs whose changed lines overlap or are adjacent, and
+    /// pairs where one hunk changes lines the other relies on as context.
+    /// This includes hunks that only have context, which assert that their
+    /// context is unchanged.
+    ///
+    /// Fails if a hunk of either diff can't be located in `base`, with the
+    /// error's user message naming the diff.
+    pub fn conflicts_with(&self, other: &FuDiff, base: &str) -> Result<Vec<Conflict>> {
+        let lines: Vec<&str> = base.lines().collect();
+        let opts = PatchOptions::default();
+        let ours = self
+            .locate(&lines, &opts)
+            .map_err(|e| e.context("This diff does not apply"))?
+            .locations;
+        let theirs = other
+            .locate(&lines, &opts)
+            .map_err(|e| e.context("The other diff does not apply"))?
+            .locations;
+        let changing = |diff: &FuDiff, l: &Location| {
+            let h = &diff.hunks[l.hunk];
+            !h.deletions.is_empty() || !h.additions.is_empty()
+        };
+
+        let mut conflicts = Vec::new();
+        for a in &ours {
+            for b in &theirs {
+                let (ra, rb) = (a.del_start..a.del_end, b.del_start..b.del_end);
+                let (ca, cb) = (changing(self, a), changing(other, b));
+                let conflict = match (ca, cb) {
+                    (true, true) => {
+                        let adjacent = ra.start <= rb.end && rb.start <= ra.end;
+                        adjacent || a.overlaps(b)
+                    }
+                    (true, false) => a.changes(b),
+                    (false, true) => b.changes(a),
+                    (false, false) => false,
+                };
+                if !conflict {
+                    continue;
+                }
+                // A hunk that only has context conflicts through its context.
+                let ours = if ca { ra } else { a.start..a.end };
+                let theirs = if cb { rb } else { b.start..b.end };
+                conflicts.push(Conflict {
+                    hunk: a.hunk,
+                    other: Some(b.hunk),
+                    message: format!(
+                        "Hunk {} {} {}, which conflicts with hunk {} of the other diff {} {}",
+                        a.hunk + 1,
+                        if ca { "changes" } else { "relies on" },
+                        describe(&ours),
+                        b.hunk + 1,
+                        if cb { "changing" } else { "relying on" },
+                        describe(&theirs)
+                    ),
+                    ours,
+                    theirs,
+                });
+            }
+        }
+        conflicts.sort_by_key(|c| (c.hunk, c.other));
+        Ok(conflicts)
+    }
+}
@@ -0,0 +1,118 @@
+//! Expressing a located diff as edits to byte ranges of its input.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::{FuDiff, PatchOptions, Result};
+
+/// A replacement of a byte range of a text.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Edit {
+    /// The bytes replaced, which always start and end on line boundaries or
+    /// at the end of a line's content.
+    pub byte_range: Range<usize>,
+    /// The text put in their place.
+    pub replacement: String,
+}
+
+/// Applies `edits`, as returned by `FuDiff::to_edits`, to `text` in place.
+/// The edits must be in order and must not overlap. Panics if an edit's range
+/// is out of bounds or doesn't fall on character boundaries.
+pub fn apply_edits(text: &mut String, edits: &[Edit]) {
+    // Edit from the end so that earlier ranges stay valid.
+    for edit in edits.iter().rev() {
+        text.replace_range(edit.byte_range.clone(), &edit.replacement);
+    }
+}
+
+/// The byte offsets at which each line of `input` starts, as split by
+/// `str::lines`, followed by `input.len()`.
+pub(crate) fn line_starts(input: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    starts.extend(input.match_indices('\n').map(|(i, _)| i + 1));
+    if input.ends_with('\n') || input.is_empty() {
+        starts.pop();
+    }
+    starts.push(input.len());
+    starts
+}
+
+impl FuDiff {
+    /// Locates this diff in `input` as `patch` does and returns the changes
+    /// as edits to byte ranges of `input`, in order and not overlapping, so
+    /// that callers can apply them to their own buffers. Each edit covers a
+    /// hunk, or a run of hunks that touch end to end. Lines outside the edits
+    /// are left exactly as they are: unlike `patch`, the edits keep CRLF line
+    /// endings and the input's trailing newline.
+    pub fn to_edits(&self, input: &str) -> Result<Vec<Edit>> {
+        self.to_edits_with(input, &PatchOptions::default())
+    }
+
+    /// Returns the changes as byte range edits, locating hunks with the given
+    /// options.
+    pub fn to_edits_with(&self, input: &str, opts: &PatchOptions) -> Result<Vec<Edit>> {
+        self.byte_edits(input, opts)
+    }
+
+    /// Locates this diff in `input` and returns an edit for each hunk, or
+    /// for each run of hunks that touch end to end, in order. Lines outside
+    /// the edits are left exactly as they are, and added lines end with the
+    /// input's line ending.
+    pub(crate) fn byte_edits(&self, input: &str, opts: &PatchOptions) -> Result<Vec<Edit>> {
+        let lines: Vec<&str> = input.lines().collect();
+        let located = self.locate(&lines, opts)?;
+        let starts = line_starts(input);
+        let n = lines.len();
+        let newline = if lines
+            .first()
+            .is_some_and(|l| input[l.len()..].starts_with("\r\n"))
+        {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        // Where line `i`'s content ends, before its line ending.
+        let content_end = |i: usize| starts[i] + lines[i].len();
+
+        // Hunks that touch end to end become a single edit, so that no edit
+        // needs to reach into another's lines.
+        let mut runs: Vec<(Range<usize>, Vec<Cow<str>>)> = Vec::new();
+        for loc in &located.locations {
+            let added = loc.additions(&self.hunks[loc.hunk], &lines);
+            match runs.last_mut() {
+                Some((del, lines)) if del.end == loc.del_start => {
+                    del.end = loc.del_end;
+                    lines.extend(added);
+                }
+                _ => runs.push((loc.del_start..loc.del_end, added)),
+            }
+        }
+
+        let mut edits = Vec::new();
+        for (del, added) in runs {
+            let mut start = starts[del.start];
+            let end = starts[del.end];
+            let replacement = if del.end < n || input.ends_with('\n') {
+                added.iter().map(|l| format!("{l}{newline}")).collect()
+            } else if added.is_empty() {
+                // Deleting the unterminated last line also takes the line
+                // ending before it.
+                if del.start > 0 && del.start < n {
+                    start = content_end(del.start - 1);
+                }
+                String::new()
+            } else if del.start < n || n == 0 {
+                added.join(newline)
+            } else {
+                format!("{newline}{}", added.join(newline))
+            };
+            if start < end || !replacement.is_empty() {
+                edits.push(Edit {
+                    byte_range: start..end,
+                    replacement,
+                });
+            }
+        }
+        Ok(edits)
+    }
+}
@@ -0,0 +1,249 @@
+//! Extracting diffs from free-form text such as chat responses.
+
+use std::ops::Range;
+
+use crate::{parse_with, FuDiff, ParseOptions, Result};
+
+/// A diff found within a larger body of text.
+#[derive(Debug)]
+pub struct Extracted {
+    /// The file the diff applies to, if the text names one.
+    pub path: Option<String>,
+    /// The byte range of the diff within the text, excluding any Markdown
+    /// fence around it.
+    pub span: Range<usize>,
+    /// The parsed diff, or the error from parsing it.
+    pub diff: Result<FuDiff>,
+}
+
+/// A line of the source text, with the byte offset it starts at.
+#[derive(Clone, Copy)]
+struct Line<'a> {
+    start: usize,
+    text: &'a str,
+}
+
+impl Line<'_> {
+    /// The byte offset just past the line's text.
+    fn end(&self) -> usize {
+        self.start + self.text.len()
+    }
+}
+
+/// Finds every diff in `text`, using default parse options. See
+/// `extract_with`.
+pub fn extract(text: &str) -> Vec<Extracted> {
+    extract_with(text, &ParseOptions::default())
+}
+
+/// Finds every diff in `text` and parses it with `opts`.
+///
+/// Diffs are recognized in Markdown code fences, and as unfenced runs of diff
+/// lines starting at a hunk or file header. Prose around them is ignored, and
+/// a fence or run holding diffs for several files yields one diff per file.
+/// A file's path is taken from its `---`/`+++` headers, or failing that from
+/// the fence's info string (```` ```diff src/main.rs ````) or from a path on
+/// the last line of prose before the diff.
+pub fn extract_with(text: &str, opts: &ParseOptions) -> Vec<Extracted> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for raw in text.split_inclusive('\n') {
+        let text = raw.trim_end_matches('\n').trim_end_matches('\r');
+        lines.push(Line { start, text });
+        start += raw.len();
+    }
+
+    let mut found = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i].text;
+        if let Some(fence) = fence(line) {
+            let info = line.trim_start()[fence.len()..].trim();
+            let close = lines[i + 1..]
+                .iter()
+                .position(|l| {
+                    let t = l.text.trim();
+                    t.starts_with(fence) && t.trim_start_matches(&fence[..1]).is_empty()
+                })
+                .map_or(lines.len(), |p| i + 1 + p);
+            let body = &lines[i + 1..close];
+            if body.iter().any(|l| l.text.starts_with("@@")) {
+                let hint = info_path(info).or_else(|| prose_path(&lines[..i]));
+                split_files(text, body, hint, opts, &mut found);
+            }
+            i = close + 1;
+        } else if starts_diff(&lines[i..]) {
+            let mut end = i + 1;
+            while end < lines.len() {
+                let l = lines[end].text;
+                // Blank lines continue the diff only if more diff follows.
+                let continues = is_diff_line(l)
+                    || (l.trim().is_empty()
+                        && lines[end..]
+                            .iter()
+                            .find(|l| !l.text.trim().is_empty())
+                            .is_some_and(|l| is_diff_line(l.text)));
+                if !continues {
+                    break;
+                }
+                end += 1;
+            }
+            let body = &lines[i..end];
+            if body.iter().any(|l| l.text.starts_with("@@")) {
+                split_files(text, body, prose_path(&lines[..i]), opts, &mut found);
+            }
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    found
+}
+
+/// Returns the fence that opens a Markdown code block on this line, if any.
+pub(crate) fn fence(line: &str) -> Option<&str> {
+    let t = line.trim_start();
+    ["```", "~~~"].into_iter().find(|f| t.starts_with(f))
+}
+
+/// Whether an unfenced diff starts at the first of `lines`.
+fn starts_diff(lines: &[Line]) -> bool {
+    let line = lines[0].text;
+    line.starts_with("@@")
+        || line.starts_with("diff --git ")
+        || (line.starts_with("--- ") && lines.get(1).is_some_and(|l| l.text.starts_with("+++ ")))
+}
+
+/// Whether a line can continue an unfenced diff.
+fn is_diff_line(line: &str) -> bool {
+    line.starts_with([' ', '-', '+', '\\'])
+        || line.starts_with("@@")
+        || line.starts_with("diff --git ")
+        || line.starts_with("index ")
+}
+
+/// Whether a line is part of a file header.
+fn is_file_header(lines: &[Line], i: usize) -> bool {
+    let line = lines[i].text;
+    line.starts_with("diff --git ")
+        || (line.starts_with("--- ")
+            && lines.get(i + 1).is_some_and(|l| l.text.starts_with("+++ ")))
+}
+
+/// Splits a block of diff lines into one diff per file, and parses each.
+fn split_files(
+    text: &str,
+    body: &[Line],
+    hint: Option<String>,
+    opts: &ParseOptions,
+    found: &mut Vec<Extracted>,
+) {
+    let mut starts = vec![0];
+    let mut seen_hunk = false;
+    for i in 0..body.len() {
+        if body[i].text.starts_with("@@") {
+            seen_hunk = true;
+        } else if seen_hunk && is_file_header(body, i) {
+            starts.push(i);
+            seen_hunk = false;
+        }
+    }
+    let single = starts.len() == 1;
+    starts.push(body.len());
+
+    for w in starts.windows(2) {
+        let file = &body[w[0]..w[1]];
+        // Parsing starts at the first header or hunk line, skipping any
+        // extended git header lines before it.
+        let Some(first) = file
+            .iter()
+            .position(|l| l.text.starts_with("---") || l.text.starts_with("@@"))
+        else {
+            continue;
+        };
+        let Some(last) = file.iter().rposition(|l| !l.text.trim().is_empty()) else {
+            continue;
+        };
+        let span = file[first].start..file[last].end();
+        let path = header_path(file).or_else(|| if single { hint.clone() } else { None });
+        found.push(Extracted {
+            path,
+            diff: parse_with(&text[span.clone()], opts),
+            span,
+        });
+    }
+}
+
+/// Takes a file path from a diff's headers, preferring the new file's name.
+fn header_path(file: &[Line]) -> Option<String> {
+    let header = |prefix: &str| {
+        file.iter()
+            .take_while(|l| !l.text.starts_with("@@"))
+            .find_map(|l| l.text.strip_prefix(prefix))
+            .map(|p| p.split('\t').next().unwrap_or(p).trim())
+            .filter(|p| !p.is_empty() && *p != "/dev/null")
+            .map(|p| {
+                p.strip_prefix("a/")
+                    .or_else(|| p.strip_prefix("b/"))
+                    .unwrap_or(p)
+                    .to_string()
+            })
+    };
+    header("+++ ").or_else(|| header("--- ")).or_else(|| {
+        file.first()?
+            .text
+            .strip_prefix("diff --git ")?
+            .split_whitespace()
+            .last()
+            .map(|p| p.strip_prefix("b/").unwrap_or(p).to_string())
+    })
+}
+
+/// Takes a file path from a fence's info string, as in `diff src/main.rs` or
+/// `diff:src/main.rs`.
+fn info_path(info: &str) -> Option<String> {
+    let rest = info
+        .split_once(':')
+        .map(|(_, p)| p)
+        .or_else(|| info.split_once(char::is_whitespace).map(|(_, p)| p))?;
+    rest.split_whitespace()
+        .map(|t| t.trim_matches('`'))
+        .find(|t| is_path(t))
+        .map(str::to_string)
+}
+
+/// Takes a file path from the last line of prose before a diff, such as
+/// ``Changes to `src/main.rs`:`` or `**src/main.rs**`.
+fn prose_path(before: &[Line]) -> Option<String> {
+    let line = before
+        .iter()
+        .rev()
+        .find(|l| !l.text.trim().is_empty())?
+        .text;
+    let quoted = line
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .filter(|t| is_path(t))
+        .last();
+    let path = quoted.or_else(|| {
+        let t = line
+            .trim()
+            .trim_start_matches('#')
+            .trim()
+            .trim_matches('*')
+            .trim_end_matches(':');
+        let t = t.strip_prefix("File:").unwrap_or(t).trim();
+        is_path(t).then_some(t)
+    })?;
+    Some(path.to_string())
+}
+
+/// Whether a string looks like a file path.
+fn is_path(s: &str) -> bool {
+    !s.is_empty()
+        && !s.contains(char::is_whitespace)
+        && (s.contains('/') || s.contains('.'))
+        && !s.ends_with('.')
+        && !s.starts_with("@@")
+}
@@ -0,0 +1,185 @@
+//! Rendering patch failures as feedback for the model that wrote the diff.
+
+use std::fmt::Write;
+
+use crate::{Diagnostic, DiagnosticKind, FuDiff, Hunk, ELISION};
+
+/// Lines of surrounding file content quoted on each side of a match.
+const SURROUNDING: usize = 2;
+
+impl FuDiff {
+    /// Renders `diagnostics` from `FuDiff::check` as a plain-text message for
+    /// the model that wrote this diff. For each failed hunk it says why the
+    /// hunk failed, quotes the nearest matching lines of `input` with
+    /// one-based line numbers, and where it can, suggests the hunk rewritten
+    /// with context copied from the input. The output depends only on its
+    /// arguments. Returns an empty string if there are no diagnostics.
+    pub fn feedback(&self, input: &str, diagnostics: &[Diagnostic]) -> String {
+        if diagnostics.is_empty() {
+            return String::new();
+        }
+        let lines: Vec<&str> = input.lines().collect();
+        let mut failed: Vec<usize> = diagnostics.iter().map(|d| d.hunk).collect();
+        failed.sort_unstable();
+        failed.dedup();
+
+        let mut out = format!(
+            "The patch could not be applied: {} of {} {} failed.\n",
+            failed.len(),
+            self.hunks.len(),
+            if self.hunks.len() == 1 {
+                "hunk"
+            } else {
+                "hunks"
+            }
+        );
+        for d in diagnostics {
+            let hunk = &self.hunks[d.hunk];
+            out.push('\n');
+            let _ = writeln!(out, "Hunk {} failed: {}", d.hunk + 1, reason(d));
+            out.push_str(&render_hunk(hunk));
+
+            match &d.kind {
+                DiagnosticKind::EmptyInput | DiagnosticKind::InvalidShape => {}
+                DiagnosticKind::Overlap { other } => {
+                    let _ = writeln!(
+                        out,
+                        "Combine hunks {} and {} into a single hunk.",
+                        other + 1,
+                        d.hunk + 1
+                    );
+                }
+                DiagnosticKind::Ambiguous { candidates } => {
+                    let span = hunk
+                        .context_before
+                        .iter()
+                        .chain(&hunk.deletions)
+                        .chain(&hunk.context_after)
+                        .filter(|l| *l != ELISION)
+                        .count()
+                        .max(1);
+                    for &c in candidates {
+                        let end = (c + span).min(lines.len());
+                        let _ = writeln!(out, "Match at lines {}-{}:", c + 1, end);
+                        quote(
+                            &mut out,
+                            &lines,
+                            c.saturating_sub(SURROUNDING),
+                            end + SURROUNDING,
+                        );
+                    }
+                    out.push_str(
+                        "Add context lines from before or after the change so that the \
+                         hunk matches only one place.\n",
+                    );
+                }
+                _ => closest(&mut out, hunk, &lines),
+            }
+        }
+        out
+    }
+}
+
+/// Explains a diagnostic in plain words.
+fn reason(d: &Diagnostic) -> String {
+    match &d.kind {
+        DiagnosticKind::EmptyInput => "it deletes lines, but the file is empty.".to_string(),
+        DiagnosticKind::NotFound => "its context lines were not found in the file.".to_string(),
+        DiagnosticKind::Ambiguous { candidates } => format!(
+            "its context matches {} places in the file.",
+            candidates.len()
+        ),
+        DiagnosticKind::DeletionMismatch { line, .. } => format!(
+            "line {} of the file does not match the line it deletes.",
+            line + 1
+        ),
+        DiagnosticKind::DeletionPastEnd => {
+            "its deleted lines run past the end of the file.".to_string()
+        }
+        DiagnosticKind::ContextMismatch { line, .. } => format!(
+            "line {} of the file does not match its context after the change.",
+            line + 1
+        ),
+        DiagnosticKind::ContextPastEnd => {
+            "its context after the change runs past the end of the file.".to_string()
+        }
+        DiagnosticKind::Overlap { other } => {
+            format!("it changes lines that hunk {} also relies on.", other + 1)
+        }
+        DiagnosticKind::InvalidShape => format!("it {}.", d.message),
+    }
+}
+
+/// Renders a single hunk, ending with a newline.
+fn render_hunk(hunk: &Hunk) -> String {
+    let mut rendered = FuDiff {
+        hunks: vec![hunk.clone()],
+    }
+    .render();
+    if !rendered.ends_with('\n') {
+        rendered.push('\n');
+    }
+    rendered
+}
+
+/// Quotes `lines[from..to]` with one-based line numbers.
+fn quote(out: &mut String, lines: &[&str], from: usize, to: usize) {
+    let to = to.min(lines.len());
+    let width = to.to_string().len();
+    for (i, line) in lines.iter().enumerate().take(to).skip(from) {
+        let _ = writeln!(out, "{:>width$} | {}", i + 1, line);
+    }
+}
+
+/// Quotes the part of the file that best matches the hunk's old lines, and
+/// suggests the hunk with those lines copied from the file.
+fn closest(out: &mut String, hunk: &Hunk, lines: &[&str]) {
+    let old: Vec<&String> = hunk
+        .context_before
+        .iter()
+        .chain(&hunk.deletions)
+        .chain(&hunk.context_after)
+        .collect();
+    // Score each position by how many non-blank lines match once surrounding
+    // whitespace is ignored. The earliest best position wins.
+    let score = |p: usize| {
+        old.iter()
+            .zip(&lines[p..])
+            .filter(|(o, l)| !o.trim().is_empty() && o.trim() == l.trim())
+            .count()
+    };
+    let best = (0..lines.len())
+        .map(|p| (score(p), p))
+        .filter(|&(s, _)| s > 0)
+        .min_by_key(|&(s, p)| (std::cmp::Reverse(s), p));
+    let Some((_, p)) = best else {
+        out.push_str("No similar lines were found in the file.\n");
+        return;
+    };
+
+    let end = (p + old.len()).min(lines.len());
+    let _ = writeln!(out, "The closest lines in the file are {}-{}:", p + 1, end);
+    quote(out, lines, p.saturating_sub(SURROUNDING), end + SURROUNDING);
+
+    let copied = |from: usize, n: usize| -> Vec<String> {
+        lines
+            .iter()
+            .skip(p + from)
+            .take(n)
+            .map(|l| l.to_string())
+            .collect()
+    };
+    let (cb, del) = (hunk.context_before.len(), hunk.deletions.len());
+    let suggested = Hunk {
+        context_before: copied(0, cb),
+        deletions: copied(cb, del),
+        additions: hunk.additions.clone(),
+        context_after: copied(cb + del, hunk.context_after.len()),
+    };
+    if suggested != *hunk && suggested.deletions.len() == del {
+        out.push_str(
+            "Retry with the hunk's unchanged and deleted lines copied exactly from the file:\n",
+        );
+        out.push_str(&render_hunk(&suggested));
+    }
+}
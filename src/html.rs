@@ -0,0 +1,201 @@
+//! HTML rendering of diffs against the text they apply to.
+
+use std::fmt::Write;
+
+use crate::view::{view, Block, Row, Side};
+use crate::{FuDiff, Granularity, Result};
+
+/// The styles embedded in every rendering.
+const STYLE: &str = "\
+.fudiff{font-family:monospace;font-size:13px}\
+.fudiff table{border-collapse:collapse;width:100%;table-layout:fixed}\
+.fudiff td{padding:0 6px;white-space:pre-wrap;word-break:break-all;vertical-align:top}\
+.fudiff td.num{width:4em;text-align:right;color:#888;user-select:none}\
+.fudiff td.mark{width:1em;user-select:none}\
+.fudiff .del{background:#ffebe9}\
+.fudiff .add{background:#e6ffec}\
+.fudiff .del mark{background:#ffc1bf}\
+.fudiff .add mark{background:#abf2bc}\
+.fudiff td.empty{background:#f6f8fa}\
+.fudiff summary{color:#888;background:#f6f8fa;cursor:pointer;padding:0 6px}";
+
+/// How the old and new text are laid out.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Layout {
+    /// One column, with deleted lines followed by the lines added in their
+    /// place.
+    #[default]
+    Unified,
+    /// Old text on the left and new text on the right, with each deleted line
+    /// beside the line that replaces it.
+    SideBySide,
+}
+
+/// Options controlling HTML rendering.
+#[derive(Debug, Clone)]
+pub struct HtmlOptions {
+    /// How the old and new text are laid out.
+    pub layout: Layout,
+    /// Highlight the changed parts of replaced lines, compared in units of
+    /// this granularity. `None` disables highlighting.
+    pub highlight: Option<Granularity>,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        HtmlOptions {
+            layout: Layout::default(),
+            highlight: Some(Granularity::Word),
+        }
+    }
+}
+
+impl FuDiff {
+    /// Renders this diff applied to `base` as a self-contained HTML fragment:
+    /// a `div` with class `fudiff` holding its own `style` element. Every
+    /// line of `base` is shown, with one-based old and new line numbers from
+    /// the located hunks. Runs of lines outside the hunks are collapsed into
+    /// `details` elements that can be expanded. Fails if the diff doesn't
+    /// apply to `base`.
+    pub fn render_html(&self, base: &str, opts: &HtmlOptions) -> Result<String> {
+        let blocks = view(self, base, opts.highlight)?;
+        let mut out = format!("<div class=\"fudiff\"><style>{STYLE}</style>\n");
+        for block in &blocks {
+            match block {
+                Block::Unchanged(rows) => {
+                    let _ = writeln!(
+                        out,
+                        "<details><summary>{} unchanged line{}</summary>",
+                        rows.len(),
+                        if rows.len() == 1 { "" } else { "s" }
+                    );
+                    table(&mut out, rows, opts.layout);
+                    out.push_str("</details>\n");
+                }
+                Block::Hunk(rows) => table(&mut out, rows, opts.layout),
+            }
+        }
+        out.push_str("</div>\n");
+        Ok(out)
+    }
+}
+
+/// Writes `rows` as a table.
+fn table(out: &mut String, rows: &[Row], layout: Layout) {
+    out.push_str("<table>\n");
+    match layout {
+        Layout::Unified => {
+            // Runs of changed rows show every deleted line, then every added
+            // line.
+            let mut i = 0;
+            while i < rows.len() {
+                if !rows[i].changed {
+                    let (old, new) = (rows[i].old.as_ref(), rows[i].new.as_ref());
+                    unified(out, old, new, "", ' ', new.or(old).unwrap());
+                    i += 1;
+                    continue;
+                }
+                let end = rows[i..]
+                    .iter()
+                    .position(|r| !r.changed)
+                    .map_or(rows.len(), |n| i + n);
+                for side in rows[i..end].iter().filter_map(|r| r.old.as_ref()) {
+                    unified(out, Some(side), None, "del", '-', side);
+                }
+                for side in rows[i..end].iter().filter_map(|r| r.new.as_ref()) {
+                    unified(out, None, Some(side), "add", '+', side);
+                }
+                i = end;
+            }
+        }
+        Layout::SideBySide => {
+            for row in rows {
+                let (del, add) = if row.changed {
+                    ("del", "add")
+                } else {
+                    ("", "")
+                };
+                out.push_str("<tr>");
+                cells(out, row.old.as_ref(), del);
+                cells(out, row.new.as_ref(), add);
+                out.push_str("</tr>\n");
+            }
+        }
+    }
+    out.push_str("</table>\n");
+}
+
+/// Writes a row of the unified layout.
+fn unified(
+    out: &mut String,
+    old: Option<&Side>,
+    new: Option<&Side>,
+    class: &str,
+    mark: char,
+    side: &Side,
+) {
+    let _ = write!(
+        out,
+        "<tr{}><td class=\"num\">{}</td><td class=\"num\">{}</td><td class=\"mark\">{}</td><td>",
+        class_attr(class),
+        number(old),
+        number(new),
+        mark
+    );
+    code(out, side);
+    out.push_str("</td></tr>\n");
+}
+
+/// Writes the line number and code cells for one side of a side-by-side row.
+fn cells(out: &mut String, side: Option<&Side>, class: &str) {
+    match side {
+        Some(side) => {
+            let _ = write!(
+                out,
+                "<td class=\"num\">{}</td><td{}>",
+                side.line + 1,
+                class_attr(class)
+            );
+            code(out, side);
+            out.push_str("</td>");
+        }
+        None => out.push_str("<td class=\"num empty\"></td><td class=\"empty\"></td>"),
+    }
+}
+
+fn class_attr(class: &str) -> String {
+    if class.is_empty() {
+        String::new()
+    } else {
+        format!(" class=\"{class}\"")
+    }
+}
+
+fn number(side: Option<&Side>) -> String {
+    side.map(|s| (s.line + 1).to_string()).unwrap_or_default()
+}
+
+/// Writes a line's text, escaped, with its changed ranges marked.
+fn code(out: &mut String, side: &Side) {
+    let mut at = 0;
+    for range in &side.changed {
+        escape(out, &side.text[at..range.start]);
+        out.push_str("<mark>");
+        escape(out, &side.text[range.clone()]);
+        out.push_str("</mark>");
+        at = range.end;
+    }
+    escape(out, &side.text[at..]);
+}
+
+fn escape(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
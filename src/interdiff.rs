@@ -0,0 +1,19 @@
+//! The difference between two revisions of a diff.
+
+use crate::lcs::unique_diff;
+use crate::{Error, FuDiff, Result};
+
+/// Returns a diff taking the output of `d1` to the output of `d2`, both
+/// applied to `base` with `FuDiff::patch`. This shows what changed between
+/// two revisions of a patch. Its hunks take context from the output of `d1`,
+/// widened until each matches only one place. Fails if either revision
+/// doesn't apply, with the error's user message naming the revision.
+pub fn interdiff(base: &str, d1: &FuDiff, d2: &FuDiff) -> Result<FuDiff> {
+    let revision =
+        |n: usize| move |e: Error| e.context(&format!("Revision {n} of the patch does not apply"));
+    let first = d1.patch(base).map_err(revision(1))?;
+    let second = d2.patch(base).map_err(revision(2))?;
+    let old: Vec<&str> = first.lines().collect();
+    let new: Vec<&str> = second.lines().collect();
+    unique_diff(&old, &new)
+}
@@ -0,0 +1,82 @@
+//! Changes within a line.
+
+use std::ops::Range;
+
+use crate::lcs::{changes, common_lines};
+use crate::Hunk;
+
+/// The unit in which lines are compared by `intraline`.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Granularity {
+    /// Words, runs of whitespace, and single punctuation characters. A word
+    /// is a run of alphanumeric characters and underscores.
+    #[default]
+    Word,
+    /// Single characters.
+    Char,
+}
+
+/// The changed parts of a deleted line and the line that replaces it, as
+/// byte ranges into each line, in order. Ranges never split a character.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Intraline {
+    /// Ranges of the old line that were removed or replaced.
+    pub old: Vec<Range<usize>>,
+    /// Ranges of the new line that were inserted or replace old text.
+    pub new: Vec<Range<usize>>,
+}
+
+/// Compares `old` and `new` in units of `granularity`, returning the ranges
+/// of each line that the other doesn't share.
+pub fn intraline(old: &str, new: &str, granularity: Granularity) -> Intraline {
+    let (a, b) = (tokens(old, granularity), tokens(new, granularity));
+    let a_text: Vec<&str> = a.iter().map(|r| &old[r.clone()]).collect();
+    let b_text: Vec<&str> = b.iter().map(|r| &new[r.clone()]).collect();
+    let pairs = common_lines(&a_text, &b_text);
+
+    // Converts a run of tokens to the bytes they cover.
+    let bytes = |tokens: &[Range<usize>], run: Range<usize>| {
+        (!run.is_empty()).then(|| tokens[run.start].start..tokens[run.end - 1].end)
+    };
+    let mut spans = Intraline::default();
+    for change in changes(&pairs, a.len(), b.len()) {
+        spans.old.extend(bytes(&a, change.old));
+        spans.new.extend(bytes(&b, change.new));
+    }
+    spans
+}
+
+/// Splits `line` into byte ranges of tokens.
+fn tokens(line: &str, granularity: Granularity) -> Vec<Range<usize>> {
+    let mut tokens: Vec<Range<usize>> = Vec::new();
+    let mut last = None;
+    for (i, c) in line.char_indices() {
+        let class = match granularity {
+            Granularity::Char => None,
+            Granularity::Word if c.is_alphanumeric() || c == '_' => Some(0),
+            Granularity::Word if c.is_whitespace() => Some(1),
+            Granularity::Word => None,
+        };
+        let end = i + c.len_utf8();
+        match tokens.last_mut() {
+            Some(t) if class.is_some() && class == last => t.end = end,
+            _ => tokens.push(i..end),
+        }
+        last = class;
+    }
+    tokens
+}
+
+impl Hunk {
+    /// Returns the changes within each line this hunk replaces. Deleted and
+    /// added lines are paired in order, so the `i`-th entry compares the
+    /// `i`-th deletion with the `i`-th addition; lines beyond the shorter of
+    /// the two lists are wholly removed or inserted and have no entry.
+    pub fn intraline(&self, granularity: Granularity) -> Vec<Intraline> {
+        self.deletions
+            .iter()
+            .zip(&self.additions)
+            .map(|(old, new)| intraline(old, new, granularity))
+            .collect()
+    }
+}
@@ -0,0 +1,157 @@
+//! Matching the lines of two texts.
+
+use std::ops::Range;
+
+use crate::{FuDiff, Hunk, PatchOptions, Result};
+
+/// Returns the pairs of lines of a longest common subsequence of `old` and
+/// `new`, in order. Lines shared at the start and end are matched directly,
+/// and the rest with a table quadratic in the size of the changed region.
+pub(crate) fn common_lines(old: &[&str], new: &[&str]) -> Vec<(usize, usize)> {
+    let prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(o, n)| o == n)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    // len[i][j] is the length of a longest common subsequence of a[i..] and
+    // b[j..].
+    let mut len = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            len[i][j] = if a[i] == b[j] {
+                len[i + 1][j + 1] + 1
+            } else {
+                len[i + 1][j].max(len[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            pairs.push((prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if len[i + 1][j] >= len[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs.extend((0..suffix).map(|k| (old.len() - suffix + k, new.len() - suffix + k)));
+    pairs
+}
+
+/// A run of lines that differs between two texts: `old` lines replaced by
+/// `new` lines. Either range may be empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Change {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// Returns the runs of lines that differ between two texts of `old_len` and
+/// `new_len` lines, given their matched lines from `common_lines`.
+pub(crate) fn changes(pairs: &[(usize, usize)], old_len: usize, new_len: usize) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for &(o, n) in pairs.iter().chain([&(old_len, new_len)]) {
+        if o > i || n > j {
+            changes.push(Change {
+                old: i..o,
+                new: j..n,
+            });
+        }
+        (i, j) = (o + 1, n + 1);
+    }
+    changes
+}
+
+/// Lines of context taken on each side of a change by `unique_diff`.
+const CONTEXT: usize = 3;
+
+/// A run of changes covered by one hunk, with the context taken on each
+/// side.
+struct Group {
+    old: Range<usize>,
+    new: Range<usize>,
+    before: usize,
+    after: usize,
+}
+
+/// Returns a diff taking `old` to `new`, with a hunk for each run of lines
+/// that differs between them. Each hunk takes `CONTEXT` lines of context on
+/// each side, widened until it matches only one place in `old`. Changes whose
+/// context would reach into each other share one hunk.
+pub(crate) fn unique_diff(old: &[&str], new: &[&str]) -> Result<FuDiff> {
+    let to_vec = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+    let mut groups: Vec<Group> = changes(&common_lines(old, new), old.len(), new.len())
+        .into_iter()
+        .map(|c| Group {
+            old: c.old,
+            new: c.new,
+            before: CONTEXT,
+            after: CONTEXT,
+        })
+        .collect();
+    loop {
+        let mut merged: Vec<Group> = Vec::with_capacity(groups.len());
+        for g in groups {
+            match merged.last_mut() {
+                Some(prev)
+                    if g.old.start.saturating_sub(g.before) < prev.old.end
+                        || prev.old.end + prev.after > g.old.start =>
+                {
+                    prev.old.end = g.old.end;
+                    prev.new.end = g.new.end;
+                    prev.after = g.after;
+                }
+                _ => merged.push(g),
+            }
+        }
+        groups = merged;
+
+        let diff = FuDiff {
+            hunks: groups
+                .iter()
+                .map(|g| Hunk {
+                    context_before: to_vec(&old[g.old.start.saturating_sub(g.before)..g.old.start]),
+                    deletions: to_vec(&old[g.old.clone()]),
+                    additions: to_vec(&new[g.new.clone()]),
+                    context_after: to_vec(&old[g.old.end..(g.old.end + g.after).min(old.len())]),
+                })
+                .collect(),
+        };
+        let mut diagnostics = diff.locate_all(old, &PatchOptions::default()).diagnostics;
+        if diagnostics.is_empty() {
+            return Ok(diff);
+        }
+        // Widen the context of ambiguous hunks until they are unique. Once
+        // the context reaches both ends of the file, the lines before the
+        // change join it, so that the hunk starts the file.
+        let mut widened = false;
+        for d in diagnostics.iter().filter(|d| d.is_ambiguous()) {
+            let g = &mut groups[d.hunk];
+            if g.before < g.old.start || g.old.end + g.after < old.len() {
+                g.before = (g.before + 1).min(g.old.start);
+                g.after = (g.after + 1).min(old.len() - g.old.end);
+                widened = true;
+            } else if g.old.start > 0 {
+                g.new.start -= g.old.start;
+                g.old.start = 0;
+                widened = true;
+            }
+        }
+        if !widened {
+            return Err(diagnostics.swap_remove(0).into());
+        }
+    }
+}
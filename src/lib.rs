@@ -1,8 +1,49 @@
 //! Implementation of the Fuzzy Unified Diff Format.
 //! This module provides functions to compute, render, parse, apply, and revert fuzzy diffs.
 
+use std::borrow::Cow;
+
+#[cfg(feature = "color")]
+mod color;
+mod compose;
+mod conflict;
+mod edit;
+mod extract;
+mod feedback;
+mod html;
+mod interdiff;
+mod intraline;
+mod lcs;
+mod line_map;
+mod locate;
+mod lsp;
+mod merge;
+mod rebase;
+mod repair;
+mod search_replace;
+mod side_by_side;
+mod stats;
 #[cfg(test)]
 mod tests;
+mod view;
+
+#[cfg(feature = "color")]
+pub use color::{ColorMode, ColorOptions};
+pub use conflict::Conflict;
+pub use edit::{apply_edits, Edit};
+pub use extract::{extract, extract_with, Extracted};
+pub use html::{HtmlOptions, Layout};
+pub use interdiff::interdiff;
+pub use intraline::{intraline, Granularity, Intraline};
+pub use line_map::LineMap;
+use locate::Location;
+pub use locate::{Diagnostic, DiagnosticKind};
+pub use lsp::{Position, PositionEncoding, TextEdit, TextRange};
+pub use merge::{merge3, ConflictRegion, Merged};
+pub use repair::Repair;
+pub use search_replace::parse_search_replace;
+pub use side_by_side::SideBySideOptions;
+pub use stats::{diffstat, Stats};
 
 /// Error type for FuDiff operations.
 #[derive(Debug)]
@@ -13,6 +54,16 @@ pub enum Error {
     Apply { user: String, details: String },
     /// Multiple possible matches found for context.
     AmbiguousMatch { user: String, details: String },
+    /// Two hunks claim overlapping regions of the input.
+    OverlappingHunks { user: String, details: String },
+    /// A hunk does not have a valid shape.
+    InvalidHunk { user: String, details: String },
+    /// Changes collide with other changes to the same text.
+    Conflict {
+        user: String,
+        details: String,
+        conflicts: Vec<Conflict>,
+    },
 }
 
 impl Error {
@@ -22,8 +73,77 @@ impl Error {
             Error::Parse { details, .. } => details,
             Error::Apply { details, .. } => details,
             Error::AmbiguousMatch { details, .. } => details,
+            Error::OverlappingHunks { details, .. } => details,
+            Error::InvalidHunk { details, .. } => details,
+            Error::Conflict { details, .. } => details,
         }
     }
+
+    /// Prefixes the user message with `context`, such as which of several
+    /// inputs failed.
+    pub(crate) fn context(self, context: &str) -> Error {
+        let prefix = |user: String| format!("{context}: {user}");
+        match self {
+            Error::Parse { user, details } => Error::Parse {
+                user: prefix(user),
+                details,
+            },
+            Error::Apply { user, details } => Error::Apply {
+                user: prefix(user),
+                details,
+            },
+            Error::AmbiguousMatch { user, details } => Error::AmbiguousMatch {
+                user: prefix(user),
+                details,
+            },
+            Error::OverlappingHunks { user, details } => Error::OverlappingHunks {
+                user: prefix(user),
+                details,
+            },
+            Error::InvalidHunk { user, details } => Error::InvalidHunk {
+                user: prefix(user),
+                details,
+            },
+            Error::Conflict {
+                user,
+                details,
+                conflicts,
+            } => Error::Conflict {
+                user: prefix(user),
+                details,
+                conflicts,
+            },
+        }
+    }
+}
+
+/// The context line that stands for an elided run of unchanged lines. Elision
+/// markers recognized by `parse_with` are stored as this line. When a hunk's
+/// context doesn't match the input literally, these lines are treated as gaps
+/// that may span any number of unchanged lines. It contains a newline, so no
+/// line of text can be mistaken for it, and it is rendered as `...`.
+pub const ELISION: &str = "\n...";
+
+/// Returns a hunk line as it is written out, with elisions as `...`.
+pub(crate) fn shown(line: &str) -> &str {
+    if line == ELISION {
+        "..."
+    } else {
+        line
+    }
+}
+
+/// Writes `c` for display on a terminal, with control characters other than
+/// tab replaced so that they can't drive the terminal: C0 controls and DEL
+/// by their Unicode control pictures, and C1 controls by `\u{..}` escapes.
+pub(crate) fn push_visible(out: &mut String, c: char) {
+    match c {
+        '\t' => out.push(c),
+        '\0'..='\x1f' => out.push(char::from_u32(0x2400 + c as u32).unwrap()),
+        '\x7f' => out.push('␡'),
+        '\u{80}'..='\u{9f}' => out.extend(c.escape_unicode()),
+        c => out.push(c),
+    }
 }
 
 /// A type alias for diff operation results.
@@ -52,11 +172,54 @@ impl std::fmt::Display for FuDiff {
     }
 }
 
+/// Options controlling how a diff is applied.
+#[derive(Debug, Clone, Default)]
+pub struct PatchOptions {
+    /// Locate every hunk against the whole input instead of searching forward
+    /// from the previous hunk. Hunks may then appear in any order, but must not
+    /// overlap.
+    pub unordered: bool,
+    /// The number of context lines that may be ignored to locate a hunk whose
+    /// context does not match the input. Deleted lines must still match. Zero
+    /// disables repair.
+    pub repair: usize,
+    /// How hunk lines are compared with input lines.
+    pub matching: Matching,
+}
+
+/// How hunk lines are compared with input lines when locating a hunk.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Matching {
+    /// Lines must be identical.
+    #[default]
+    Exact,
+    /// Lines may differ by a common shift in leading indentation, measured in
+    /// columns with tabs advancing to the next multiple of four. Exact matches
+    /// are tried first. When a hunk matches with a shift, its additions are
+    /// re-indented by the same shift, using tabs if the matched input lines
+    /// are indented with tabs.
+    Indent,
+}
+
+/// The result of applying a diff, with a report of how it was applied.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Patched {
+    /// The patched text.
+    pub output: String,
+    /// Context lines that were ignored to locate hunks.
+    pub repairs: Vec<Repair>,
+}
+
 impl FuDiff {
-    /// Reverts the changes represented by this diff from the given input.
-    /// This swaps additions with deletions and applies the patch.
-    pub fn revert(&self, input: &str) -> Result<String> {
-        let reverted = FuDiff {
+    /// Returns the inverse of this diff, which undoes its changes: each
+    /// hunk's additions and deletions swap places, and its context is kept.
+    /// Inverting twice gives back the original diff.
+    ///
+    /// Hunks don't record whether the file ends with a newline, and parsing
+    /// drops the line numbers of hunk headers, so the inverse carries
+    /// neither: see `revert` for how the trailing newline is handled.
+    pub fn invert(&self) -> FuDiff {
+        FuDiff {
             hunks: self
                 .hunks
                 .iter()
@@ -67,115 +230,191 @@ impl FuDiff {
                     context_after: h.context_after.clone(),
                 })
                 .collect(),
-        };
+        }
+    }
 
-        reverted.patch(input)
+    /// Reverts the changes represented by this diff from the given input, by
+    /// applying its inverse. The output keeps the input's trailing newline,
+    /// so reverting a patch that kept its input's trailing newline gives back
+    /// that input exactly.
+    ///
+    /// Diffs don't record whether a file ends with a newline, so a patch that
+    /// adds or removes the trailing newline can't be undone: the reverted
+    /// lines are the original's, but the newline follows the input's.
+    pub fn revert(&self, input: &str) -> Result<String> {
+        self.invert()
+            .apply(input, &PatchOptions::default(), false)
+            .map(|p| p.output)
     }
 
     /// Applies this diff to the provided input text, returning the patched result.
     /// Returns an error if the patch cannot be applied cleanly.
     pub fn patch(&self, input: &str) -> Result<String> {
-        if self.hunks.is_empty() {
-            return Ok(input.to_string());
-        }
-
-        let lines: Vec<&str> = input.lines().collect();
-        if lines.is_empty() && self.hunks.iter().any(|h| !h.deletions.is_empty()) {
-            return Err(Error::Apply {
-                user: "Failed to apply patch".to_string(),
-                details: "Cannot apply patch to empty input".to_string(),
-            });
-        }
-
-        let mut result = Vec::new();
-        let mut pos = 0;
-
-        for hunk in &self.hunks {
-            let hunk_pos = if hunk.context_before.is_empty() {
-                pos
-            } else {
-                let mut candidate = None;
-                for i in pos..=lines.len().saturating_sub(hunk.context_before.len()) {
-                    if hunk
-                        .context_before
-                        .iter()
-                        .enumerate()
-                        .all(|(j, ctx)| lines[i + j] == ctx)
-                    {
-                        if candidate.is_some() {
-                            return Err(Error::AmbiguousMatch {
-                                user: "Multiple matching contexts found".to_string(),
-                                details: format!(
-                                    "Multiple matches for context: {:?}",
-                                    hunk.context_before
-                                ),
-                            });
-                        }
-                        candidate = Some(i);
-                    }
-                }
-                candidate.ok_or_else(|| Error::Apply {
-                    user: "Failed to apply patch".to_string(),
-                    details: format!("Could not find context: {:?}", hunk.context_before),
-                })?
-            };
+        self.patch_with(input, &PatchOptions::default())
+    }
 
-            let deletion_start = hunk_pos + hunk.context_before.len();
-            if !hunk.deletions.is_empty() {
-                if deletion_start + hunk.deletions.len() > lines.len() {
-                    return Err(Error::Apply {
-                        user: "Failed to apply patch".to_string(),
-                        details: "Deletion extends past end of file".to_string(),
-                    });
-                }
-                for (i, deletion) in hunk.deletions.iter().enumerate() {
-                    if lines[deletion_start + i] != deletion {
-                        return Err(Error::Apply {
-                            user: "Failed to apply patch".to_string(),
-                            details: format!(
-                                "Deletion mismatch at line {} - expected '{}', found '{}'",
-                                deletion_start + i + 1,
-                                deletion,
-                                lines[deletion_start + i]
-                            ),
-                        });
-                    }
-                }
-            }
+    /// Applies this diff to the provided input text using the given options.
+    pub fn patch_with(&self, input: &str, opts: &PatchOptions) -> Result<String> {
+        self.patch_report(input, opts).map(|p| p.output)
+    }
 
-            if pos < hunk_pos {
-                result.extend(lines[pos..hunk_pos].iter().map(|s| s.to_string()));
-            }
-            result.extend(
-                hunk.context_before
-                    .iter()
-                    .enumerate()
-                    .map(|(i, _)| lines[hunk_pos + i].to_string()),
-            );
-            result.extend(hunk.additions.iter().cloned());
+    /// Applies this diff to the provided input text using the given options,
+    /// reporting any repairs made along the way.
+    pub fn patch_report(&self, input: &str, opts: &PatchOptions) -> Result<Patched> {
+        self.apply(input, opts, true)
+    }
 
-            pos = deletion_start + hunk.deletions.len();
+    /// Applies this diff to `input`. With `trim_newline`, a final hunk that
+    /// only deletes lines also removes the input's trailing newline;
+    /// otherwise the output keeps the input's trailing newline.
+    fn apply(&self, input: &str, opts: &PatchOptions, trim_newline: bool) -> Result<Patched> {
+        if self.hunks.is_empty() {
+            return Ok(Patched {
+                output: input.to_string(),
+                repairs: Vec::new(),
+            });
         }
 
-        if pos < lines.len() {
-            result.extend(lines[pos..].iter().map(|s| s.to_string()));
+        let lines: Vec<&str> = input.lines().collect();
+        let located = self.locate(&lines, opts)?;
+        let locations = &located.locations;
+
+        // Splice from the bottom up so earlier locations stay valid.
+        let mut result: Vec<Cow<str>> = lines.iter().map(|l| Cow::Borrowed(*l)).collect();
+        for loc in locations.iter().rev() {
+            result.splice(
+                loc.del_start..loc.del_end,
+                loc.additions(&self.hunks[loc.hunk], &lines),
+            );
         }
 
         let mut output = result.join("\n");
         if !result.is_empty() && input.contains('\n') && input.ends_with('\n') {
-            let last_hunk = self.hunks.last().unwrap();
+            let last_hunk = &self.hunks[locations.last().unwrap().hunk];
             // Append newline only if the last hunk did not remove the trailing newline.
-            if last_hunk.deletions.is_empty()
+            if !trim_newline
+                || last_hunk.deletions.is_empty()
                 || !last_hunk.additions.is_empty()
                 || !last_hunk.context_after.is_empty()
             {
                 output.push('\n');
             }
         }
-        Ok(output)
+        Ok(Patched {
+            output,
+            repairs: located.repairs,
+        })
+    }
+
+    /// Checks whether this diff applies to `input` without building the
+    /// output, reporting every problem rather than stopping at the first.
+    pub fn check(&self, input: &str) -> Vec<Diagnostic> {
+        self.check_with(input, &PatchOptions::default())
+    }
+
+    /// Checks whether this diff applies to `input` using the given options.
+    /// Hunks with an invalid shape are reported first, then problems locating
+    /// hunks.
+    pub fn check_with(&self, input: &str, opts: &PatchOptions) -> Vec<Diagnostic> {
+        let lines: Vec<&str> = input.lines().collect();
+        let mut diagnostics: Vec<Diagnostic> = self
+            .shape_problems()
+            .map(|(hunk, problem)| Diagnostic {
+                hunk,
+                kind: DiagnosticKind::InvalidShape,
+                message: problem.to_string(),
+            })
+            .collect();
+        diagnostics.extend(self.locate_all(&lines, opts).diagnostics);
+        diagnostics
+    }
+
+    /// Checks that every hunk has a valid shape. A hunk with only context
+    /// lines is a valid no-op that asserts the context is present. A hunk with
+    /// only additions or only deletions must have context to anchor it, unless
+    /// it is the only hunk in the diff and so applies to the whole file.
+    pub fn validate(&self) -> Result<()> {
+        match self.shape_problems().next() {
+            Some((i, problem)) => Err(Error::InvalidHunk {
+                user: "Invalid hunk".to_string(),
+                details: format!("Hunk {} {}", i + 1, problem),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the index of each hunk with an invalid shape, with the
+    /// problem.
+    fn shape_problems(&self) -> impl Iterator<Item = (usize, &'static str)> + '_ {
+        self.hunks.iter().enumerate().filter_map(|(i, hunk)| {
+            let has_context = !hunk.context_before.is_empty() || !hunk.context_after.is_empty();
+            let problem = match (
+                has_context,
+                hunk.deletions.is_empty(),
+                hunk.additions.is_empty(),
+            ) {
+                (false, true, true) => "is empty",
+                (false, true, false) if self.hunks.len() > 1 => {
+                    "adds lines without any context to anchor them"
+                }
+                (false, false, true) if self.hunks.len() > 1 => {
+                    "deletes lines without any context to anchor them"
+                }
+                _ => return None,
+            };
+            Some((i, problem))
+        })
+    }
+
+    /// Returns an equivalent diff for `input` with the hunks in file order, and
+    /// with hunks whose located regions overlap or touch merged into one. Lines
+    /// between merged changes are carried as both deletions and additions.
+    pub fn normalize(&self, input: &str, opts: &PatchOptions) -> Result<FuDiff> {
+        let lines: Vec<&str> = input.lines().collect();
+        let to_strings = |r: std::ops::Range<usize>| lines[r].iter().map(|s| s.to_string());
+
+        let mut hunks: Vec<Hunk> = Vec::new();
+        let mut prev: Option<Location> = None;
+        for loc in self.locate(&lines, opts)?.locations {
+            let hunk = &self.hunks[loc.hunk];
+            match (prev.as_mut(), hunks.last_mut()) {
+                (Some(p), Some(merged)) if loc.start <= p.end => {
+                    merged.deletions.extend(to_strings(p.del_end..loc.del_end));
+                    merged
+                        .additions
+                        .extend(to_strings(p.del_end..loc.del_start));
+                    merged
+                        .additions
+                        .extend(loc.additions(hunk, &lines).into_iter().map(Cow::into_owned));
+                    p.del_end = loc.del_end;
+                    p.end = p.end.max(loc.end);
+                    merged.context_after = to_strings(p.del_end..p.end).collect();
+                }
+                _ => {
+                    // Take context and deletions from the input, in case the
+                    // hunk was repaired or matched with an indent shift.
+                    hunks.push(Hunk {
+                        context_before: to_strings(loc.start..loc.del_start).collect(),
+                        deletions: to_strings(loc.del_start..loc.del_end).collect(),
+                        additions: loc
+                            .additions(hunk, &lines)
+                            .into_iter()
+                            .map(Cow::into_owned)
+                            .collect(),
+                        context_after: to_strings(loc.del_end..loc.end).collect(),
+                    });
+                    prev = Some(loc);
+                }
+            }
+        }
+        Ok(FuDiff { hunks })
     }
 
     /// Renders the diff into a unified diff format string.
+    ///
+    /// Elisions are rendered as `...`, which `parse` reads as a literal
+    /// context line. To get them back, parse with `parse_with` and `...`
+    /// among the `ParseOptions::elisions`.
     pub fn render(&self) -> String {
         let mut output = String::new();
 
@@ -184,7 +423,7 @@ impl FuDiff {
 
             for line in &hunk.context_before {
                 output.push(' ');
-                output.push_str(line);
+                output.push_str(shown(line));
                 output.push('\n');
             }
 
@@ -202,7 +441,7 @@ impl FuDiff {
 
             for (j, line) in hunk.context_after.iter().enumerate() {
                 output.push(' ');
-                output.push_str(line);
+                output.push_str(shown(line));
                 if i < self.hunks.len() - 1 || j < hunk.context_after.len() - 1 {
                     output.push('\n');
                 }
@@ -324,15 +563,142 @@ pub fn diff(old: &str, new: &str) -> FuDiff {
     FuDiff { hunks }
 }
 
+/// How strictly `parse_with` interprets the diff text.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ParseMode {
+    /// Accept lines without a diff marker as context, and skip empty lines.
+    #[default]
+    Normal,
+    /// Require every hunk line to carry a marker, reject changes that follow a
+    /// hunk's trailing context, and validate the shape of every hunk. Hunk
+    /// headers must be bare, and CRLF line endings are rejected.
+    Strict,
+    /// Recover from common formatting mistakes, reporting each recovery as a
+    /// `ParseWarning`.
+    Lenient,
+}
+
+/// Options controlling how diff text is parsed.
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// How strictly to interpret the diff text.
+    pub mode: ParseMode,
+    /// Context lines that mark elided unchanged code, such as
+    /// `// ... existing code ...`. A line matches a marker if they are equal
+    /// once surrounding whitespace is trimmed, with or without a leading
+    /// space marker. Matching lines are parsed as `ELISION`.
+    pub elisions: Vec<String>,
+}
+
+/// A formatting mistake recovered from while parsing in lenient mode.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseWarning {
+    /// The zero-based line of the diff text.
+    pub line: usize,
+    /// What was recovered from.
+    pub kind: ParseWarningKind,
+    /// A human-readable description of the recovery.
+    pub message: String,
+}
+
+/// The kinds of mistake recovered from by `ParseMode::Lenient`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ParseWarningKind {
+    /// A context line had no leading space, or was left empty.
+    MissingSpace,
+    /// A diff marker was indented with tabs.
+    IndentedMarker,
+    /// A hunk header had text after it.
+    HeaderText,
+    /// The diff text uses CRLF line endings.
+    CrLf,
+    /// A Markdown code fence was skipped.
+    Fence,
+    /// Text before the first hunk was skipped.
+    LeadingText,
+    /// Explanatory text after the last hunk was skipped.
+    TrailingText,
+}
+
+/// The result of parsing diff text, with any warnings.
+#[derive(Debug, Clone)]
+pub struct Parsed {
+    /// The parsed diff.
+    pub diff: FuDiff,
+    /// The formatting mistakes recovered from, in line order.
+    pub warnings: Vec<ParseWarning>,
+}
+
+/// Whether a hunk header is bare: `@@ @@`, or unified ranges such as
+/// `@@ -1,4 +1,5 @@`, with nothing after it.
+fn is_bare_header(line: &str) -> bool {
+    let is_range = |s: &str, sign: char| {
+        s.strip_prefix(sign).is_some_and(|r| {
+            r.split(',').count() <= 2
+                && r.split(',')
+                    .all(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        })
+    };
+    let Some(rest) = line.strip_prefix("@@") else {
+        return false;
+    };
+    let Some((ranges, tail)) = rest.split_once("@@") else {
+        return rest.trim().is_empty();
+    };
+    tail.trim().is_empty()
+        && match ranges.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [] => true,
+            [old, new] => is_range(old, '-') && is_range(new, '+'),
+            _ => false,
+        }
+}
+
+/// Returns the line where explanatory text after the last hunk begins: the
+/// first blank line or fence after the last hunk header whose next line of
+/// text has no diff marker. Anything after it, such as a bulleted list, is
+/// text. Returns `lines.len()` if there is no such text.
+fn trailing_text(lines: &[&str]) -> usize {
+    let Some(last_header) = lines.iter().rposition(|l| l.starts_with("@@")) else {
+        return lines.len();
+    };
+    let is_break = |l: &str| l.trim().is_empty() || extract::fence(l).is_some();
+    (last_header + 1..lines.len())
+        .find(|&k| {
+            is_break(lines[k])
+                && lines[k + 1..]
+                    .iter()
+                    .find(|l| !is_break(l))
+                    .is_some_and(|l| !l.starts_with([' ', '-', '+', '\t', '\\']))
+        })
+        .unwrap_or(lines.len())
+}
+
 /// Parses a unified diff format string into a FuDiff.
 /// Returns an error if no valid hunks are found or if parsing fails.
 pub fn parse(input: &str) -> Result<FuDiff> {
+    parse_with(input, &ParseOptions::default())
+}
+
+/// Parses a unified diff format string into a FuDiff using the given options.
+pub fn parse_with(input: &str, opts: &ParseOptions) -> Result<FuDiff> {
+    parse_report(input, opts).map(|p| p.diff)
+}
+
+/// Parses a unified diff format string into a FuDiff using the given options,
+/// reporting the mistakes recovered from in lenient mode.
+pub fn parse_report(input: &str, opts: &ParseOptions) -> Result<Parsed> {
+    let strict = opts.mode == ParseMode::Strict;
+    let lenient = opts.mode == ParseMode::Lenient;
     let mut hunks = Vec::new();
     let mut current_hunk = None;
+    let mut warnings = Vec::new();
 
     // Empty input signifies a diff with no changes.
     if input.trim().is_empty() {
-        return Ok(FuDiff { hunks: vec![] });
+        return Ok(Parsed {
+            diff: FuDiff { hunks: vec![] },
+            warnings,
+        });
     }
 
     // Non-empty input must contain hunk markers.
@@ -343,8 +709,78 @@ pub fn parse(input: &str) -> Result<FuDiff> {
         });
     }
 
-    for line in input.lines() {
+    let is_elision = |line: &str| {
+        let text = line.trim();
+        !text.is_empty() && opts.elisions.iter().any(|m| m.trim() == text)
+    };
+    let parse_error = |lineno: usize, msg: &str| Error::Parse {
+        user: "Failed to parse diff".to_string(),
+        details: format!("Line {}: {}", lineno + 1, msg),
+    };
+    let mut warn = |line: usize, kind: ParseWarningKind, message: &str| {
+        warnings.push(ParseWarning {
+            line,
+            kind,
+            message: format!("Line {}: {}", line + 1, message),
+        })
+    };
+
+    if let Some(pos) = input.find("\r\n") {
+        let lineno = input[..pos].matches('\n').count();
+        if strict {
+            return Err(parse_error(lineno, "Line ends with CRLF"));
+        }
+        if lenient {
+            warn(
+                lineno,
+                ParseWarningKind::CrLf,
+                "Diff uses CRLF line endings",
+            );
+        }
+    }
+
+    let lines: Vec<&str> = input.lines().collect();
+    let text_end = if lenient {
+        trailing_text(&lines)
+    } else {
+        lines.len()
+    };
+    let body_end = lines[..text_end]
+        .iter()
+        .rposition(|l| !l.trim().is_empty())
+        .map_or(0, |i| i + 1);
+    let mut leading_text = false;
+    for (lineno, &line) in lines[..text_end].iter().enumerate() {
+        let mut line = line;
+        if lenient {
+            if extract::fence(line).is_some() {
+                warn(lineno, ParseWarningKind::Fence, "Skipped Markdown fence");
+                continue;
+            }
+            let unindented = line.trim_start_matches('\t');
+            if unindented.len() < line.len() && unindented.starts_with(['-', '+']) {
+                warn(
+                    lineno,
+                    ParseWarningKind::IndentedMarker,
+                    "Diff marker is indented with tabs",
+                );
+                line = unindented;
+            }
+        }
+
         if line.starts_with("@@") {
+            if !is_bare_header(line) {
+                if strict {
+                    return Err(parse_error(lineno, "Hunk header has trailing text"));
+                }
+                if lenient {
+                    warn(
+                        lineno,
+                        ParseWarningKind::HeaderText,
+                        "Ignored text in hunk header",
+                    );
+                }
+            }
             // Finalize the previous hunk and start a new one.
             if let Some(hunk) = current_hunk.take() {
                 hunks.push(hunk);
@@ -364,16 +800,74 @@ pub fn parse(input: &str) -> Result<FuDiff> {
         }
 
         // Ensure the line is within a hunk.
-        let hunk = current_hunk.as_mut().ok_or_else(|| Error::Parse {
-            user: "Failed to parse diff".to_string(),
-            details: "Line found outside of hunk".to_string(),
-        })?;
+        let Some(hunk) = current_hunk.as_mut() else {
+            if lenient {
+                if !leading_text && !line.trim().is_empty() {
+                    warn(
+                        lineno,
+                        ParseWarningKind::LeadingText,
+                        "Skipped text before the first hunk",
+                    );
+                    leading_text = true;
+                }
+                continue;
+            }
+            return Err(Error::Parse {
+                user: "Failed to parse diff".to_string(),
+                details: "Line found outside of hunk".to_string(),
+            });
+        };
 
         if line.is_empty() {
+            if strict && lineno < body_end {
+                return Err(parse_error(lineno, "Empty line has no diff marker"));
+            }
+            // An empty line within a hunk body is a blank context line that
+            // lost its space. Blank lines between hunks are skipped.
+            if lenient
+                && lines[(lineno + 1).min(body_end)..body_end]
+                    .iter()
+                    .find(|l| !l.trim().is_empty())
+                    .is_some_and(|l| !l.starts_with("@@") && extract::fence(l).is_none())
+            {
+                warn(
+                    lineno,
+                    ParseWarningKind::MissingSpace,
+                    "Empty line treated as blank context",
+                );
+                if hunk.deletions.is_empty() && hunk.additions.is_empty() {
+                    hunk.context_before.push(String::new());
+                } else {
+                    hunk.context_after.push(String::new());
+                }
+            }
+            continue;
+        }
+
+        if !line.starts_with(['-', '+']) && is_elision(line) {
+            if hunk.deletions.is_empty() && hunk.additions.is_empty() {
+                hunk.context_before.push(ELISION.to_string());
+            } else {
+                hunk.context_after.push(ELISION.to_string());
+            }
             continue;
         }
 
-        let (marker, content) = line.split_at(1);
+        // A change after an elision in trailing context starts a new hunk,
+        // with the elision as a leading gap.
+        if line.starts_with(['-', '+']) {
+            if let Some(gap) = hunk.context_after.iter().rposition(|l| l == ELISION) {
+                let next = Hunk {
+                    context_before: hunk.context_after.split_off(gap),
+                    deletions: Vec::new(),
+                    additions: Vec::new(),
+                    context_after: Vec::new(),
+                };
+                hunks.push(std::mem::replace(hunk, next));
+            }
+        }
+
+        let (marker, content) = line.split_at(line.chars().next().map_or(0, char::len_utf8));
         match marker {
             " " => {
                 if hunk.deletions.is_empty() && hunk.additions.is_empty() {
@@ -382,9 +876,28 @@ pub fn parse(input: &str) -> Result<FuDiff> {
                     hunk.context_after.push(content.to_string());
                 }
             }
+            "-" | "+" if strict && !hunk.context_after.is_empty() => {
+                return Err(parse_error(
+                    lineno,
+                    "Change follows the hunk's trailing context; start a new hunk",
+                ));
+            }
             "-" => hunk.deletions.push(content.to_string()),
             "+" => hunk.additions.push(content.to_string()),
+            _ if strict => {
+                return Err(parse_error(
+                    lineno,
+                    &format!("Line has no diff marker: {:?}", line),
+                ));
+            }
             _ => {
+                if lenient {
+                    warn(
+                        lineno,
+                        ParseWarningKind::MissingSpace,
+                        "Line has no diff marker; treated as context",
+                    );
+                }
                 // Lines that don't start with a diff marker are treated as context
                 if hunk.deletions.is_empty() && hunk.additions.is_empty() {
                     hunk.context_before.push(line.to_string());
@@ -394,11 +907,27 @@ pub fn parse(input: &str) -> Result<FuDiff> {
             }
         }
     }
+    for (lineno, line) in lines.iter().enumerate().skip(text_end) {
+        if extract::fence(line).is_some() {
+            warn(lineno, ParseWarningKind::Fence, "Skipped Markdown fence");
+        } else if !line.trim().is_empty() {
+            warn(
+                lineno,
+                ParseWarningKind::TrailingText,
+                "Skipped text after the last hunk",
+            );
+            break;
+        }
+    }
 
     // Append the final hunk if present.
     if let Some(hunk) = current_hunk.take() {
         hunks.push(hunk);
     }
 
-    Ok(FuDiff { hunks })
+    let diff = FuDiff { hunks };
+    if strict {
+        diff.validate()?;
+    }
+    Ok(Parsed { diff, warnings })
 }
@@ -0,0 +1,63 @@
+//! Mapping line numbers between a text and its patched version.
+
+use crate::{FuDiff, PatchOptions, Result};
+
+/// Where each line of a text ends up after patching, and where each line of
+/// the patched text came from. Line numbers are zero-based.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct LineMap {
+    /// For each line of the input, its line in the output, or `None` if the
+    /// patch deleted it.
+    pub old_to_new: Vec<Option<usize>>,
+    /// For each line of the output, its line in the input, or `None` if the
+    /// patch added it.
+    pub new_to_old: Vec<Option<usize>>,
+}
+
+impl LineMap {
+    /// Returns the output line that input line `old` became, or `None` if it
+    /// was deleted or is past the end of the input.
+    pub fn new_line(&self, old: usize) -> Option<usize> {
+        self.old_to_new.get(old).copied().flatten()
+    }
+
+    /// Returns the input line that output line `new` came from, or `None` if
+    /// it was added or is past the end of the output.
+    pub fn old_line(&self, new: usize) -> Option<usize> {
+        self.new_to_old.get(new).copied().flatten()
+    }
+}
+
+impl FuDiff {
+    /// Locates this diff in `input` as `patch` does and maps the lines of
+    /// `input` to the lines of the patched output, and back.
+    pub fn line_map(&self, input: &str) -> Result<LineMap> {
+        self.line_map_with(input, &PatchOptions::default())
+    }
+
+    /// Maps lines between `input` and the patched output, locating hunks with
+    /// the given options.
+    pub fn line_map_with(&self, input: &str, opts: &PatchOptions) -> Result<LineMap> {
+        let lines: Vec<&str> = input.lines().collect();
+        let located = self.locate(&lines, opts)?;
+
+        let mut map = LineMap::default();
+        let mut old = 0;
+        for loc in &located.locations {
+            for line in old..loc.del_start {
+                map.old_to_new.push(Some(map.new_to_old.len()));
+                map.new_to_old.push(Some(line));
+            }
+            map.old_to_new
+                .extend(std::iter::repeat_n(None, loc.del_end - loc.del_start));
+            let added = self.hunks[loc.hunk].additions.len();
+            map.new_to_old.extend(std::iter::repeat_n(None, added));
+            old = loc.del_end;
+        }
+        for line in old..lines.len() {
+            map.old_to_new.push(Some(map.new_to_old.len()));
+            map.new_to_old.push(Some(line));
+        }
+        Ok(map)
+    }
+}
@@ -0,0 +1,614 @@
+//! Locating hunks within the input text.
+
+use std::borrow::Cow;
+
+use crate::repair::{repair_hunk, Repair};
+use crate::{Error, FuDiff, Hunk, Matching, PatchOptions, ELISION};
+
+/// Columns a tab advances to when measuring indentation.
+const TAB_WIDTH: usize = 4;
+
+/// A problem found while locating a hunk in the input.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// Index of the hunk within the diff.
+    pub hunk: usize,
+    /// What went wrong.
+    pub kind: DiagnosticKind,
+    /// A human-readable description of the problem.
+    pub message: String,
+}
+
+/// The kinds of problem reported by `FuDiff::check`. Line numbers are
+/// zero-based indices into the input.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DiagnosticKind {
+    /// The hunk deletes lines, but the input is empty.
+    EmptyInput,
+    /// The hunk's context could not be found.
+    NotFound,
+    /// The hunk's context matches at more than one place.
+    Ambiguous { candidates: Vec<usize> },
+    /// A deleted line does not match the input.
+    DeletionMismatch {
+        line: usize,
+        expected: String,
+        found: String,
+    },
+    /// The deletions run past the end of the input.
+    DeletionPastEnd,
+    /// A trailing context line does not match the input.
+    ContextMismatch {
+        line: usize,
+        expected: String,
+        found: String,
+    },
+    /// The trailing context runs past the end of the input.
+    ContextPastEnd,
+    /// The hunk changes lines that another hunk relies on.
+    Overlap { other: usize },
+    /// The hunk has an invalid shape, as reported by `FuDiff::validate`.
+    InvalidShape,
+}
+
+impl Diagnostic {
+    pub(crate) fn is_ambiguous(&self) -> bool {
+        matches!(self.kind, DiagnosticKind::Ambiguous { .. })
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "hunk {}: {}", self.hunk + 1, self.message)
+    }
+}
+
+impl From<Diagnostic> for Error {
+    fn from(d: Diagnostic) -> Self {
+        match d.kind {
+            DiagnosticKind::Ambiguous { .. } => Error::AmbiguousMatch {
+                user: "Multiple matching contexts found".to_string(),
+                details: d.message,
+            },
+            DiagnosticKind::Overlap { .. } => Error::OverlappingHunks {
+                user: "Hunks overlap".to_string(),
+                details: d.message,
+            },
+            DiagnosticKind::InvalidShape => Error::InvalidHunk {
+                user: "Invalid hunk".to_string(),
+                details: format!("Hunk {} {}", d.hunk + 1, d.message),
+            },
+            _ => Error::Apply {
+                user: "Failed to apply patch".to_string(),
+                details: d.message,
+            },
+        }
+    }
+}
+
+/// The region of the input a hunk was matched against, as line indices.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Location {
+    /// Index of the hunk within the diff.
+    pub hunk: usize,
+    /// First line of the leading context.
+    pub start: usize,
+    /// First deleted line.
+    pub del_start: usize,
+    /// One past the last deleted line.
+    pub del_end: usize,
+    /// One past the last line of the trailing context.
+    pub end: usize,
+    /// The indent shift, in columns, from the hunk's lines to the input's, if
+    /// the hunk was matched with `Matching::Indent`.
+    pub shift: Option<isize>,
+}
+
+/// The outcome of locating every hunk of a diff.
+#[derive(Debug, Default)]
+pub(crate) struct Located {
+    /// Locations of the hunks that were found, sorted by position.
+    pub locations: Vec<Location>,
+    /// Context lines ignored to locate hunks in repair mode.
+    pub repairs: Vec<Repair>,
+    /// Problems with hunks that could not be located or that overlap.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Location {
+    pub(crate) fn new(
+        index: usize,
+        hunk: &Hunk,
+        start: usize,
+        del_start: usize,
+        len: usize,
+        shift: Option<isize>,
+    ) -> Self {
+        let del_end = del_start + hunk.deletions.len();
+        Location {
+            hunk: index,
+            start,
+            del_start,
+            del_end,
+            end: (del_end + hunk.context_after.len()).min(len),
+            shift,
+        }
+    }
+
+    /// Returns the hunk's additions as they should be inserted into `lines`,
+    /// re-indented to follow the input if the hunk was matched with an indent
+    /// shift.
+    pub(crate) fn additions<'a>(&self, hunk: &'a Hunk, lines: &[&str]) -> Vec<Cow<'a, str>> {
+        let Some(shift) = self.shift else {
+            return hunk
+                .additions
+                .iter()
+                .map(|s| Cow::Borrowed(s.as_str()))
+                .collect();
+        };
+        let tabs = lines[self.start..self.end]
+            .iter()
+            .find(|l| l.starts_with([' ', '\t']))
+            .is_some_and(|l| l.starts_with('\t'));
+        hunk.additions
+            .iter()
+            .map(|line| reindent(line, shift, tabs))
+            .collect()
+    }
+
+    /// Whether this hunk changes any line that `other` relies on. Hunks may
+    /// share context lines, and may insert at the edges of each other.
+    pub(crate) fn changes(&self, other: &Location) -> bool {
+        if self.del_start == self.del_end {
+            other.start < self.del_start && self.del_start < other.end
+        } else {
+            self.del_start < other.end && other.start < self.del_end
+        }
+    }
+
+    /// Whether two located hunks conflict with each other.
+    pub(crate) fn overlaps(&self, other: &Location) -> bool {
+        self.changes(other) || other.changes(self)
+    }
+}
+
+impl FuDiff {
+    /// Locates every hunk in `lines`, failing with the first problem found.
+    pub(crate) fn locate(&self, lines: &[&str], opts: &PatchOptions) -> crate::Result<Located> {
+        let mut located = self.locate_all(lines, opts);
+        if located.diagnostics.is_empty() {
+            Ok(located)
+        } else {
+            Err(located.diagnostics.swap_remove(0).into())
+        }
+    }
+
+    /// Locates every hunk in `lines`, carrying on past failures.
+    pub(crate) fn locate_all(&self, lines: &[&str], opts: &PatchOptions) -> Located {
+        let mut located = Located::default();
+        let mut pos = 0;
+        for (i, hunk) in self.hunks.iter().enumerate() {
+            let from = if opts.unordered { 0 } else { pos };
+            let elided = hunk
+                .context_before
+                .iter()
+                .chain(&hunk.context_after)
+                .any(|l| l == ELISION);
+            let attempt = |matching| {
+                let result = locate_hunk(i, hunk, lines, from, !opts.unordered, matching);
+                match result {
+                    Err(ref d) if elided && !d.is_ambiguous() => {
+                        let anchored = !opts.unordered && i > 0;
+                        match locate_elided(i, hunk, lines, from, anchored, matching) {
+                            Err(e) if !e.is_ambiguous() => result,
+                            elided => elided,
+                        }
+                    }
+                    _ => result,
+                }
+            };
+
+            let mut result = attempt(Matching::Exact);
+            if opts.matching != Matching::Exact && result.as_ref().is_err_and(|d| !d.is_ambiguous())
+            {
+                if let Ok(loc) = attempt(opts.matching) {
+                    result = Ok(loc);
+                }
+            }
+            match result {
+                Ok(loc) => {
+                    pos = loc.del_end;
+                    located.locations.push(loc);
+                }
+                Err(d) if opts.repair > 0 && !d.is_ambiguous() => {
+                    match repair_hunk(i, hunk, lines, from, opts) {
+                        Some((loc, repairs)) => {
+                            pos = loc.del_end;
+                            located.locations.push(loc);
+                            located.repairs.extend(repairs);
+                        }
+                        None => located.diagnostics.push(d),
+                    }
+                }
+                Err(d) => located.diagnostics.push(d),
+            }
+        }
+
+        let locations = &mut located.locations;
+        locations.sort_by_key(|loc| (loc.start, loc.del_end));
+        for (i, a) in locations.iter().enumerate() {
+            for b in &locations[i + 1..] {
+                if a.overlaps(b) {
+                    let (first, second) = if a.hunk < b.hunk { (a, b) } else { (b, a) };
+                    located.diagnostics.push(Diagnostic {
+                        hunk: second.hunk,
+                        kind: DiagnosticKind::Overlap { other: first.hunk },
+                        message: format!(
+                            "Hunk {} (lines {}-{}) overlaps hunk {} (lines {}-{})",
+                            first.hunk + 1,
+                            first.start + 1,
+                            first.end,
+                            second.hunk + 1,
+                            second.start + 1,
+                            second.end
+                        ),
+                    });
+                }
+            }
+        }
+        located
+    }
+}
+
+/// Returns the width of a line's leading whitespace in columns.
+fn indent_width(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .fold(0, |w, c| {
+            if c == '\t' {
+                w - w % TAB_WIDTH + TAB_WIDTH
+            } else {
+                w + 1
+            }
+        })
+}
+
+/// Shifts a line's indentation by `shift` columns, writing the indentation
+/// with tabs or spaces to match the input. Lines that need no change are
+/// returned as they are.
+fn reindent(line: &str, shift: isize, tabs: bool) -> Cow<'_, str> {
+    let body = line.trim_start();
+    let indent = &line[..line.len() - body.len()];
+    let uniform = if tabs {
+        indent.chars().all(|c| c == '\t')
+    } else {
+        indent.chars().all(|c| c == ' ')
+    };
+    if body.is_empty() || (shift == 0 && uniform) {
+        return Cow::Borrowed(line);
+    }
+    let width = (indent_width(line) as isize + shift).max(0) as usize;
+    let indent = if tabs {
+        "\t".repeat(width / TAB_WIDTH) + &" ".repeat(width % TAB_WIDTH)
+    } else {
+        " ".repeat(width)
+    };
+    Cow::Owned(indent + body)
+}
+
+/// Compares a hunk line against an input line. With `Matching::Indent`,
+/// lines match if they differ only by an indent shift, and the first
+/// non-blank pair fixes the shift that every later pair must share.
+pub(crate) fn lines_match(
+    matching: Matching,
+    expected: &str,
+    found: &str,
+    shift: &mut Option<isize>,
+) -> bool {
+    match matching {
+        Matching::Exact => expected == found,
+        Matching::Indent => {
+            let (e, f) = (expected.trim_start(), found.trim_start());
+            if e.is_empty() || f.is_empty() {
+                return e.is_empty() && f.is_empty();
+            }
+            if e != f {
+                return false;
+            }
+            let delta = indent_width(found) as isize - indent_width(expected) as isize;
+            *shift.get_or_insert(delta) == delta
+        }
+    }
+}
+
+/// Returns every index at or after `from` where `needle` matches `lines`,
+/// along with the indent shift it matched with.
+fn find_matches(
+    lines: &[&str],
+    needle: &[&String],
+    from: usize,
+    matching: Matching,
+) -> Vec<(usize, Option<isize>)> {
+    if needle.len() > lines.len() {
+        return Vec::new();
+    }
+    (from..=lines.len() - needle.len())
+        .filter_map(|i| {
+            let mut shift = None;
+            needle
+                .iter()
+                .enumerate()
+                .all(|(j, l)| lines_match(matching, l, lines[i + j], &mut shift))
+                .then_some((i, shift))
+        })
+        .collect()
+}
+
+/// Checks that the hunk's deletions, followed by its trailing context, are
+/// present at `del_start`, sharing the indent shift of any earlier match.
+fn check_body(
+    index: usize,
+    hunk: &Hunk,
+    lines: &[&str],
+    del_start: usize,
+    matching: Matching,
+    shift: &mut Option<isize>,
+) -> Result<(), Diagnostic> {
+    let diagnostic = |kind, message| Diagnostic {
+        hunk: index,
+        kind,
+        message,
+    };
+
+    if del_start + hunk.deletions.len() > lines.len() {
+        return Err(diagnostic(
+            DiagnosticKind::DeletionPastEnd,
+            "Deletion extends past end of file".to_string(),
+        ));
+    }
+    for (i, deletion) in hunk.deletions.iter().enumerate() {
+        let line = del_start + i;
+        if !lines_match(matching, deletion, lines[line], shift) {
+            return Err(diagnostic(
+                DiagnosticKind::DeletionMismatch {
+                    line,
+                    expected: deletion.clone(),
+                    found: lines[line].to_string(),
+                },
+                format!(
+                    "Deletion mismatch at line {} - expected '{}', found '{}'",
+                    line + 1,
+                    deletion,
+                    lines[line]
+                ),
+            ));
+        }
+    }
+
+    let ctx_start = del_start + hunk.deletions.len();
+    for (i, ctx) in hunk.context_after.iter().enumerate() {
+        let line = ctx_start + i;
+        match lines.get(line) {
+            Some(found) if lines_match(matching, ctx, found, shift) => {}
+            Some(found) => {
+                return Err(diagnostic(
+                    DiagnosticKind::ContextMismatch {
+                        line,
+                        expected: ctx.clone(),
+                        found: found.to_string(),
+                    },
+                    format!(
+                        "Context mismatch at line {} - expected '{}', found '{}'",
+                        line + 1,
+                        ctx,
+                        found
+                    ),
+                ))
+            }
+            None => {
+                return Err(diagnostic(
+                    DiagnosticKind::ContextPastEnd,
+                    "Context extends past end of file".to_string(),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Locates a single hunk in `lines`, searching from line `from` onward. With
+/// `ordered`, a hunk without leading context that fits at `from` is placed
+/// there, just after the previous hunk; otherwise it must match only once.
+/// A hunk that only inserts, without any context, is always placed at `from`.
+fn locate_hunk(
+    index: usize,
+    hunk: &Hunk,
+    lines: &[&str],
+    from: usize,
+    ordered: bool,
+    matching: Matching,
+) -> Result<Location, Diagnostic> {
+    if lines.is_empty() && !hunk.deletions.is_empty() {
+        return Err(Diagnostic {
+            hunk: index,
+            kind: DiagnosticKind::EmptyInput,
+            message: "Cannot apply patch to empty input".to_string(),
+        });
+    }
+    let located = |start, del_start, shift: Option<isize>| {
+        let shift = match matching {
+            Matching::Exact => None,
+            Matching::Indent => Some(shift.unwrap_or(0)),
+        };
+        Location::new(index, hunk, start, del_start, lines.len(), shift)
+    };
+
+    if hunk.context_before.is_empty() {
+        // Without leading context the hunk is anchored by its deletions and
+        // trailing context. Prefer `from` in ordered mode, then search
+        // further on. A hunk with neither only inserts, at `from`, since it
+        // would match everywhere.
+        let mut shift = None;
+        let fits = check_body(index, hunk, lines, from, matching, &mut shift);
+        let needle: Vec<&String> = hunk.deletions.iter().chain(&hunk.context_after).collect();
+        if (ordered || needle.is_empty()) && fits.is_ok() {
+            return Ok(located(from, from, shift));
+        }
+        let candidates = find_matches(lines, &needle, from, matching);
+        return match candidates.as_slice() {
+            [(i, shift)] => Ok(located(*i, *i, *shift)),
+            [] => fits.map(|()| located(from, from, shift)),
+            _ => Err(Diagnostic {
+                hunk: index,
+                kind: DiagnosticKind::Ambiguous {
+                    candidates: candidates.iter().map(|(i, _)| *i).collect(),
+                },
+                message: format!("Multiple matches for deletions and context: {:?}", needle),
+            }),
+        };
+    }
+
+    let needle: Vec<&String> = hunk.context_before.iter().collect();
+    let candidates = find_matches(lines, &needle, from, matching);
+    let (start, mut shift) = match candidates.as_slice() {
+        [c] => *c,
+        [] => {
+            return Err(Diagnostic {
+                hunk: index,
+                kind: DiagnosticKind::NotFound,
+                message: format!("Could not find context: {:?}", hunk.context_before),
+            })
+        }
+        _ => {
+            return Err(Diagnostic {
+                hunk: index,
+                kind: DiagnosticKind::Ambiguous {
+                    candidates: candidates.iter().map(|(i, _)| *i).collect(),
+                },
+                message: format!("Multiple matches for context: {:?}", hunk.context_before),
+            })
+        }
+    };
+    let del_start = start + hunk.context_before.len();
+    check_body(index, hunk, lines, del_start, matching, &mut shift)?;
+    Ok(located(start, del_start, shift))
+}
+
+/// Splits context lines into the runs between elision markers.
+fn segments(context: &[String]) -> Vec<Vec<&String>> {
+    context
+        .split(|l| l == ELISION)
+        .map(|run| run.iter().collect())
+        .collect()
+}
+
+/// Checks whether `needle` matches `lines` at `i` under an existing indent
+/// shift, returning the shift if it does.
+fn matches_at(
+    lines: &[&str],
+    needle: &[&String],
+    i: usize,
+    matching: Matching,
+    shift: Option<isize>,
+) -> Option<Option<isize>> {
+    let mut shift = shift;
+    (i + needle.len() <= lines.len()
+        && needle
+            .iter()
+            .enumerate()
+            .all(|(j, l)| lines_match(matching, l, lines[i + j], &mut shift)))
+    .then_some(shift)
+}
+
+/// Locates a hunk whose context contains elision markers. The context next to
+/// the change is matched together with the deletions, and each run of context
+/// beyond an elision is matched at its nearest position on the far side of
+/// the gap, within `from` and the end of the input. When `anchored`, a
+/// leading elision is a gap from `from`, the end of the previous hunk.
+fn locate_elided(
+    index: usize,
+    hunk: &Hunk,
+    lines: &[&str],
+    from: usize,
+    anchored: bool,
+    matching: Matching,
+) -> Result<Location, Diagnostic> {
+    let before = segments(&hunk.context_before);
+    let after = segments(&hunk.context_after);
+    let (outer_before, core_before) = before.split_at(before.len() - 1);
+    let (core_after, outer_after) = after.split_at(1.min(after.len()));
+    let core_before = &core_before[0];
+    let needle: Vec<&String> = core_before
+        .iter()
+        .copied()
+        .chain(&hunk.deletions)
+        .chain(core_after.iter().flatten().copied())
+        .collect();
+
+    let place = |i: usize, shift: Option<isize>| -> Option<Location> {
+        let mut shift = shift;
+        let mut start = i;
+        for run in outer_before.iter().rev() {
+            let last = start.checked_sub(run.len()).filter(|&j| j >= from)?;
+            (start, shift) = (from..=last)
+                .rev()
+                .find_map(|j| matches_at(lines, run, j, matching, shift).map(|s| (j, s)))?;
+        }
+        if anchored && outer_before.first().is_some_and(|run| run.is_empty()) {
+            start = from;
+        }
+        let del_start = i + core_before.len();
+        let del_end = del_start + hunk.deletions.len();
+        let mut end = i + needle.len();
+        for run in outer_after {
+            let (j, s) = (end..=lines.len().saturating_sub(run.len()))
+                .find_map(|j| matches_at(lines, run, j, matching, shift).map(|s| (j, s)))?;
+            (end, shift) = (j + run.len(), s);
+        }
+        Some(Location {
+            hunk: index,
+            start,
+            del_start,
+            del_end,
+            end,
+            shift: match matching {
+                Matching::Exact => None,
+                Matching::Indent => Some(shift.unwrap_or(0)),
+            },
+        })
+    };
+
+    let cores = if needle.is_empty() {
+        Vec::new()
+    } else {
+        find_matches(lines, &needle, from, matching)
+    };
+    // Gaps are as short as possible: a placement whose gap spans another
+    // match of the core is passed over in favour of that match.
+    let placed: Vec<Location> = cores
+        .iter()
+        .filter_map(|&(i, shift)| place(i, shift))
+        .filter(|loc| {
+            !cores.iter().any(|&(c, _)| {
+                c != loc.del_start - core_before.len()
+                    && loc.start <= c
+                    && c + needle.len() <= loc.end
+            })
+        })
+        .collect();
+    match placed.as_slice() {
+        [loc] => Ok(*loc),
+        [] => Err(Diagnostic {
+            hunk: index,
+            kind: DiagnosticKind::NotFound,
+            message: format!("Could not find context around elision: {:?}", needle),
+        }),
+        _ => Err(Diagnostic {
+            hunk: index,
+            kind: DiagnosticKind::Ambiguous {
+                candidates: placed.iter().map(|l| l.start).collect(),
+            },
+            message: format!("Multiple matches for context around elision: {:?}", needle),
+        }),
+    }
+}
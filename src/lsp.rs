@@ -0,0 +1,92 @@
+//! Language Server Protocol text edits.
+
+use crate::edit::line_starts;
+use crate::{FuDiff, PatchOptions, Result};
+
+/// The code units in which `Position::character` counts.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum PositionEncoding {
+    /// UTF-16 code units, the LSP default.
+    #[default]
+    Utf16,
+    /// UTF-8 code units, that is, bytes.
+    Utf8,
+}
+
+/// A position in a text, as in LSP: a zero-based line, and a zero-based
+/// offset into that line in code units of the chosen encoding.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// A range of a text between two positions, end exclusive.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextRange {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// A replacement of a range of a text, as an LSP `TextEdit`. With the `serde`
+/// feature it serializes to the LSP JSON shape.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextEdit {
+    pub range: TextRange,
+    #[cfg_attr(feature = "serde", serde(rename = "newText"))]
+    pub new_text: String,
+}
+
+impl FuDiff {
+    /// Locates this diff in `input` as `patch` does and returns the changes
+    /// as LSP text edits, in order and not overlapping. Each edit covers a
+    /// hunk, or a run of hunks that touch end to end. Lines outside the edits
+    /// are left exactly as they are: unlike `patch`, the edits keep CRLF line
+    /// endings and the input's trailing newline.
+    pub fn text_edits(&self, input: &str, encoding: PositionEncoding) -> Result<Vec<TextEdit>> {
+        self.text_edits_with(input, &PatchOptions::default(), encoding)
+    }
+
+    /// Returns the changes as LSP text edits, locating hunks with the given
+    /// options.
+    pub fn text_edits_with(
+        &self,
+        input: &str,
+        opts: &PatchOptions,
+        encoding: PositionEncoding,
+    ) -> Result<Vec<TextEdit>> {
+        // Only lines that exist can be positioned in: a final line ending
+        // starts an empty last line, but the end of the input doesn't
+        // otherwise.
+        let mut starts = line_starts(input);
+        if !input.ends_with('\n') && starts.len() > 1 {
+            starts.pop();
+        }
+        let position = |offset: usize| {
+            let line = starts.partition_point(|&s| s <= offset) - 1;
+            let text = &input[starts[line]..offset];
+            let character = match encoding {
+                PositionEncoding::Utf16 => text.encode_utf16().count(),
+                PositionEncoding::Utf8 => text.len(),
+            };
+            Position {
+                line: line as u32,
+                character: character as u32,
+            }
+        };
+        Ok(self
+            .byte_edits(input, opts)?
+            .into_iter()
+            .map(|e| TextEdit {
+                range: TextRange {
+                    start: position(e.byte_range.start),
+                    end: position(e.byte_range.end),
+                },
+                new_text: e.replacement,
+            })
+            .collect())
+    }
+}
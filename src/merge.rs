@@ -0,0 +1,93 @@
+//! Three-way merging of text.
+
+use std::ops::Range;
+
+use crate::lcs::common_lines;
+
+/// The result of a three-way merge.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Merged {
+    /// The merged text. Conflicting regions are written between
+    /// `<<<<<<< ours`, `=======` and `>>>>>>> theirs` markers.
+    pub output: String,
+    /// The conflicting regions, in order. Empty if the merge is clean.
+    pub conflicts: Vec<ConflictRegion>,
+}
+
+/// A region that both sides of a merge changed differently. Line ranges are
+/// zero-based indices into each text.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConflictRegion {
+    /// The lines of the base that both sides changed.
+    pub base: Range<usize>,
+    /// Our replacement for those lines.
+    pub ours: Range<usize>,
+    /// Their replacement for those lines.
+    pub theirs: Range<usize>,
+    /// The lines of the output from the `<<<<<<<` marker to the `>>>>>>>`
+    /// marker, inclusive.
+    pub output: Range<usize>,
+}
+
+/// Merges the changes that `ours` and `theirs` each make to `base`. Changes
+/// to different lines are combined; where both sides change the same lines,
+/// identical changes are taken once and differing ones are written out with
+/// conflict markers and reported as conflicts.
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> Merged {
+    let b: Vec<&str> = base.lines().collect();
+    let o: Vec<&str> = ours.lines().collect();
+    let t: Vec<&str> = theirs.lines().collect();
+
+    // Base lines kept by both sides split the texts into chunks that are
+    // merged independently.
+    let mut to_theirs = vec![None; b.len()];
+    for (i, k) in common_lines(&b, &t) {
+        to_theirs[i] = Some(k);
+    }
+    let stable: Vec<(usize, usize, usize)> = common_lines(&b, &o)
+        .into_iter()
+        .filter_map(|(i, j)| Some((i, j, to_theirs[i]?)))
+        .chain([(b.len(), o.len(), t.len())])
+        .collect();
+
+    let mut lines: Vec<&str> = Vec::new();
+    let mut conflicts = Vec::new();
+    let (mut bi, mut oi, mut ti) = (0, 0, 0);
+    for (i, j, k) in stable {
+        let (bc, oc, tc) = (&b[bi..i], &o[oi..j], &t[ti..k]);
+        if oc == bc || oc == tc {
+            lines.extend(tc);
+        } else if tc == bc {
+            lines.extend(oc);
+        } else {
+            let start = lines.len();
+            lines.push("<<<<<<< ours");
+            lines.extend(oc);
+            lines.push("=======");
+            lines.extend(tc);
+            lines.push(">>>>>>> theirs");
+            conflicts.push(ConflictRegion {
+                base: bi..i,
+                ours: oi..j,
+                theirs: ti..k,
+                output: start..lines.len(),
+            });
+        }
+        if i < b.len() {
+            lines.push(b[i]);
+        }
+        (bi, oi, ti) = (i + 1, j + 1, k + 1);
+    }
+
+    // Take whichever side changed the trailing newline.
+    let newline = if ours.ends_with('\n') == base.ends_with('\n') {
+        theirs.ends_with('\n')
+    } else {
+        ours.ends_with('\n')
+    };
+    let mut output = lines.join("\n");
+    if newline && !lines.is_empty() {
+        output.push('\n');
+    }
+    Merged { output, conflicts }
+}
@@ -0,0 +1,152 @@
+//! Rebasing a diff onto a changed base.
+
+use std::ops::Range;
+
+use crate::conflict::{collides, describe};
+use crate::lcs::{changes, common_lines};
+use crate::{Conflict, Error, FuDiff, Hunk, PatchOptions, Result};
+
+/// A rebased hunk's position in the new base, with the amount of context
+/// taken on each side, and the number of lines before its deletions folded
+/// into its change.
+struct Placement {
+    hunk: usize,
+    del: Range<usize>,
+    before: usize,
+    after: usize,
+    fold: usize,
+}
+
+impl FuDiff {
+    /// Transforms this diff, which applies to `base_old`, into one that makes
+    /// the same changes to `base_new`. Each hunk keeps its deletions and
+    /// additions, and takes its context from `base_new`, with more lines if
+    /// needed to keep it unambiguous. A hunk that is still ambiguous once its
+    /// context reaches the previous hunk takes the lines before its change
+    /// into it.
+    ///
+    /// Fails with `Error::Conflict` if lines this diff deletes were changed
+    /// between `base_old` and `base_new`, or if this diff inserts lines
+    /// inside such a change.
+    pub fn rebase(&self, base_old: &str, base_new: &str) -> Result<FuDiff> {
+        let old: Vec<&str> = base_old.lines().collect();
+        let new: Vec<&str> = base_new.lines().collect();
+        let located = self.locate(&old, &PatchOptions::default())?;
+        let pairs = common_lines(&old, &new);
+        let upstream = changes(&pairs, old.len(), new.len());
+
+        let mut conflicts = Vec::new();
+        for loc in &located.locations {
+            let ours = loc.del_start..loc.del_end;
+            for change in upstream.iter().filter(|c| collides(&ours, &c.old)) {
+                conflicts.push(Conflict {
+                    hunk: loc.hunk,
+                    other: None,
+                    ours: ours.clone(),
+                    theirs: change.old.clone(),
+                    message: format!(
+                        "Hunk {} changes {}, which overlaps {} changed upstream",
+                        loc.hunk + 1,
+                        describe(&ours),
+                        describe(&change.old)
+                    ),
+                });
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(Error::Conflict {
+                user: "Patch conflicts with upstream changes".to_string(),
+                details: conflicts
+                    .iter()
+                    .map(|c| c.message.as_str())
+                    .collect::<Vec<_>>()
+                    .join("; "),
+                conflicts,
+            });
+        }
+
+        // Where each line, or the point before it, lands in the new base.
+        let mut map = vec![0; old.len() + 1];
+        let (mut o, mut n) = (0, 0);
+        for &(po, pn) in pairs.iter().chain([&(old.len(), new.len())]) {
+            // Points within a run of changed lines land after the run's
+            // replacement, except at its start, which lands before it.
+            map[o..=po].fill(pn);
+            if o < po {
+                map[o] = n;
+            }
+            (o, n) = (po + 1, pn + 1);
+        }
+
+        // Each hunk is looked for after the previous one's deletions, so its
+        // context before can't reach back past them, and its context after
+        // can't reach into the next one's.
+        let mut placements: Vec<Placement> = Vec::with_capacity(located.locations.len());
+        for loc in &located.locations {
+            let start = map[loc.del_start];
+            let limit = placements.last().map_or(0, |p: &Placement| p.del.end);
+            placements.push(Placement {
+                hunk: loc.hunk,
+                del: start..start + (loc.del_end - loc.del_start),
+                before: (loc.del_start - loc.start).min(start.saturating_sub(limit)),
+                after: loc.end - loc.del_end,
+                fold: 0,
+            });
+        }
+        let to_vec = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        let end_limit = |placements: &[Placement], i: usize| {
+            placements
+                .get(i + 1)
+                .map_or(new.len(), |next| next.del.start - next.fold)
+        };
+        loop {
+            let rebased = FuDiff {
+                hunks: placements
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| {
+                        let start = p.del.start - p.fold;
+                        let end = (p.del.end + p.after).min(end_limit(&placements, i));
+                        Hunk {
+                            context_before: to_vec(&new[start - p.before..start]),
+                            deletions: to_vec(&new[start..p.del.end]),
+                            additions: to_vec(&new[start..p.del.start])
+                                .into_iter()
+                                .chain(self.hunks[p.hunk].additions.iter().cloned())
+                                .collect(),
+                            context_after: to_vec(&new[p.del.end..end.max(p.del.end)]),
+                        }
+                    })
+                    .collect(),
+            };
+            let mut diagnostics = rebased
+                .locate_all(&new, &PatchOptions::default())
+                .diagnostics;
+            if diagnostics.is_empty() {
+                return Ok(rebased);
+            }
+            // Widen the context of ambiguous hunks until they are unique. Once
+            // it reaches the hunks on either side, or the ends of the file, the
+            // lines before the change join it, as in `unique_diff`.
+            let mut widened = false;
+            for d in diagnostics.iter().filter(|d| d.is_ambiguous()) {
+                let limit = d.hunk.checked_sub(1).map_or(0, |i| placements[i].del.end);
+                let end_limit = end_limit(&placements, d.hunk).max(placements[d.hunk].del.end);
+                let p = &mut placements[d.hunk];
+                let start = p.del.start - p.fold;
+                if start - p.before > limit || p.del.end + p.after < end_limit {
+                    p.before = (p.before + 1).min(start - limit);
+                    p.after = (p.after + 1).min(end_limit - p.del.end);
+                    widened = true;
+                } else if start > limit {
+                    p.fold = p.del.start - limit;
+                    p.before = 0;
+                    widened = true;
+                }
+            }
+            if !widened {
+                return Err(diagnostics.swap_remove(0).into());
+            }
+        }
+    }
+}
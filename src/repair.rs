@@ -0,0 +1,193 @@
+//! Repairing hunks whose context does not quite match the input.
+
+use crate::locate::{lines_match, Location};
+use crate::{Hunk, Matching, PatchOptions};
+
+/// A context line that was ignored to locate a hunk.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Repair {
+    /// Index of the hunk within the diff.
+    pub hunk: usize,
+    /// The ignored context line.
+    pub context: String,
+    /// The zero-based input line the context line stood in for, or `None` if
+    /// it was dropped without standing in for any line.
+    pub line: Option<usize>,
+}
+
+/// The cost of an alignment: the number of context lines ignored, and how many
+/// of those were dropped outright. Lower is better.
+type Cost = (usize, usize);
+
+/// How a run of context lines lines up against the input.
+struct Alignment {
+    cost: Cost,
+    /// Number of input lines covered.
+    len: usize,
+    /// Index of each ignored context line, with the offset of the input line
+    /// it stood in for.
+    ignored: Vec<(usize, Option<usize>)>,
+}
+
+/// Replaces the cost in `cell` if `c` is cheaper.
+fn relax(cell: &mut Option<Cost>, c: Cost) {
+    if cell.is_none_or(|old| c < old) {
+        *cell = Some(c);
+    }
+}
+
+/// Aligns `context` against `input`, consuming input lines in order. Each
+/// context line either matches the next input line, stands in for it, or is
+/// dropped. Returns the cheapest alignment that ignores at most `budget` lines.
+fn align(
+    context: &[&String],
+    input: &[&str],
+    budget: usize,
+    eq: impl Fn(&str, &str) -> bool,
+) -> Option<Alignment> {
+    let m = context.len();
+    let n = input.len().min(m);
+    let step = |(bad, dropped): Cost, i: usize, j: usize| {
+        if eq(context[i], input[j]) {
+            (bad, dropped)
+        } else {
+            (bad + 1, dropped)
+        }
+    };
+
+    // cost[i][j] is the cheapest alignment of the first i context lines with
+    // the first j input lines.
+    let mut cost: Vec<Vec<Option<Cost>>> = vec![vec![None; n + 1]; m + 1];
+    cost[0][0] = Some((0, 0));
+    for i in 0..m {
+        for j in 0..=n {
+            let Some(c) = cost[i][j] else { continue };
+            relax(&mut cost[i + 1][j], (c.0 + 1, c.1 + 1));
+            if j < n {
+                relax(&mut cost[i + 1][j + 1], step(c, i, j));
+            }
+        }
+    }
+
+    let (len, best) = (0..=n)
+        .filter_map(|j| cost[m][j].map(|c| (j, c)))
+        .min_by_key(|&(j, c)| (c, std::cmp::Reverse(j)))?;
+    if best.0 > budget {
+        return None;
+    }
+
+    let mut ignored = Vec::new();
+    let (mut i, mut j) = (m, len);
+    while i > 0 {
+        let here = cost[i][j];
+        if j > 0 && cost[i - 1][j - 1].map(|c| step(c, i - 1, j - 1)) == here {
+            if !eq(context[i - 1], input[j - 1]) {
+                ignored.push((i - 1, Some(j - 1)));
+            }
+            j -= 1;
+        } else {
+            ignored.push((i - 1, None));
+        }
+        i -= 1;
+    }
+    ignored.reverse();
+    Some(Alignment {
+        cost: best,
+        len,
+        ignored,
+    })
+}
+
+/// Locates a hunk whose context does not match the input exactly. The
+/// deletions must match, and anchor the hunk together with the context lines
+/// that do match. At most `opts.repair` context lines may be ignored, and the
+/// best location must be unique. With `Matching::Indent`, the context must
+/// share the indent shift of the deletions.
+pub(crate) fn repair_hunk(
+    index: usize,
+    hunk: &Hunk,
+    lines: &[&str],
+    from: usize,
+    opts: &PatchOptions,
+) -> Option<(Location, Vec<Repair>)> {
+    let budget = opts.repair;
+    let before: Vec<&String> = hunk.context_before.iter().rev().collect();
+    let after: Vec<&String> = hunk.context_after.iter().collect();
+    let k = hunk.deletions.len();
+    if from + k > lines.len() {
+        return None;
+    }
+
+    let mut best: Option<(Cost, usize, isize, Alignment, Alignment)> = None;
+    let mut tied = false;
+    for p in from..=lines.len() - k {
+        let mut shift = None;
+        if !hunk
+            .deletions
+            .iter()
+            .zip(&lines[p..])
+            .all(|(d, l)| lines_match(opts.matching, d, l, &mut shift))
+        {
+            continue;
+        }
+        let shift = shift.unwrap_or(0);
+        let eq = |c: &str, l: &str| lines_match(opts.matching, c, l, &mut Some(shift));
+        let preceding: Vec<&str> = lines[from.max(p.saturating_sub(before.len()))..p]
+            .iter()
+            .rev()
+            .copied()
+            .collect();
+        let Some(a) = align(&before, &preceding, budget, eq) else {
+            continue;
+        };
+        let following = &lines[p + k..lines.len().min(p + k + after.len())];
+        let Some(b) = align(&after, following, budget - a.cost.0, eq) else {
+            continue;
+        };
+        let matched = before.len() + after.len() + k - a.ignored.len() - b.ignored.len();
+        if matched == 0 {
+            continue;
+        }
+
+        let cost = (a.cost.0 + b.cost.0, a.cost.1 + b.cost.1);
+        match &best {
+            Some((c, ..)) if cost > *c => {}
+            Some((c, ..)) if cost == *c => tied = true,
+            _ => {
+                best = Some((cost, p, shift, a, b));
+                tied = false;
+            }
+        }
+    }
+    if tied {
+        return None;
+    }
+
+    let (_, p, shift, a, b) = best?;
+    let m = before.len();
+    let mut repairs: Vec<Repair> = a
+        .ignored
+        .iter()
+        .rev()
+        .map(|&(i, offset)| Repair {
+            hunk: index,
+            context: hunk.context_before[m - 1 - i].clone(),
+            line: offset.map(|o| p - 1 - o),
+        })
+        .collect();
+    repairs.extend(b.ignored.iter().map(|&(i, offset)| Repair {
+        hunk: index,
+        context: hunk.context_after[i].clone(),
+        line: offset.map(|o| p + k + o),
+    }));
+
+    let loc = Location {
+        hunk: index,
+        start: p - a.len,
+        del_start: p,
+        del_end: p + k,
+        end: p + k + b.len,
+        shift: (opts.matching == Matching::Indent).then_some(shift),
+    };
+    Some((loc, repairs))
+}
@@ -0,0 +1,166 @@
+//! The search/replace block format used by many coding assistants:
+//!
+//! ```text
+//! <<<<<<< SEARCH
+//! fn main() {
+//!     old();
+//! =======
+//! fn main() {
+//!     new();
+//! >>>>>>> REPLACE
+//! ```
+//!
+//! Each block becomes a hunk. Lines shared by the start or end of both sides
+//! become context, and the rest become deletions and additions.
+
+use crate::{shown, Error, FuDiff, Hunk, Result};
+
+const SEARCH: &str = "<<<<<<< SEARCH";
+const DIVIDER: &str = "=======";
+const REPLACE: &str = ">>>>>>> REPLACE";
+
+/// Where the parser is within a block.
+enum State {
+    Outside,
+    Search,
+    Replace,
+}
+
+/// Parses search/replace blocks into a FuDiff. Text outside the blocks, such
+/// as prose or file names, is ignored.
+pub fn parse_search_replace(input: &str) -> Result<FuDiff> {
+    let parse_error = |lineno: usize, msg: &str| Error::Parse {
+        user: "Failed to parse search/replace blocks".to_string(),
+        details: format!("Line {}: {}", lineno + 1, msg),
+    };
+
+    let mut blocks: Vec<(Vec<&str>, Vec<&str>)> = Vec::new();
+    let mut state = State::Outside;
+    let mut search = Vec::new();
+    let mut replace = Vec::new();
+    let mut opened = 0;
+    for (lineno, line) in input.lines().enumerate() {
+        let marker = line.trim_end();
+        match state {
+            State::Outside if marker == SEARCH => {
+                state = State::Search;
+                opened = lineno;
+            }
+            State::Outside => {}
+            State::Search if marker == DIVIDER => state = State::Replace,
+            State::Search if marker == REPLACE => {
+                return Err(parse_error(lineno, "Replace marker before divider"));
+            }
+            State::Search => search.push(line),
+            State::Replace if marker == REPLACE => {
+                blocks.push((std::mem::take(&mut search), std::mem::take(&mut replace)));
+                state = State::Outside;
+            }
+            State::Replace if marker == SEARCH || marker == DIVIDER => {
+                return Err(parse_error(
+                    lineno,
+                    "Unexpected marker inside replace block",
+                ));
+            }
+            State::Replace => replace.push(line),
+        }
+    }
+    if !matches!(state, State::Outside) {
+        return Err(parse_error(opened, "Search/replace block is not closed"));
+    }
+    // The lines a block ends with that the next one starts with are where
+    // the next hunk is looked for, so they stay context where they can.
+    let hunks: Vec<Hunk> = blocks
+        .iter()
+        .enumerate()
+        .map(|(i, (search, replace))| {
+            let shared = blocks.get(i + 1).map_or(0, |(next, _)| {
+                (1..=search.len().min(next.len()))
+                    .rev()
+                    .find(|&k| search[search.len() - k..] == next[..k])
+                    .unwrap_or(0)
+            });
+            block_hunk(search, replace, shared)
+        })
+        .collect();
+    if hunks.is_empty() && !input.trim().is_empty() {
+        return Err(Error::Parse {
+            user: "Failed to parse search/replace blocks".to_string(),
+            details: "No search/replace blocks found".to_string(),
+        });
+    }
+    Ok(FuDiff { hunks })
+}
+
+/// Builds the hunk that turns `search` into `replace`, taking lines shared by
+/// the start or end of both as context. Up to `keep` lines shared by the end
+/// are taken first, then those shared by the start.
+fn block_hunk(search: &[&str], replace: &[&str], keep: usize) -> Hunk {
+    let to_vec = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+    let common_suffix = |s: &[&str], r: &[&str]| {
+        s.iter()
+            .rev()
+            .zip(r.iter().rev())
+            .take_while(|(s, r)| s == r)
+            .count()
+    };
+    let kept = common_suffix(search, replace).min(keep);
+    let (search_rest, replace_rest) = (
+        &search[..search.len() - kept],
+        &replace[..replace.len() - kept],
+    );
+    let prefix = search_rest
+        .iter()
+        .zip(replace_rest)
+        .take_while(|(s, r)| s == r)
+        .count();
+    let suffix = kept + common_suffix(&search_rest[prefix..], &replace_rest[prefix..]);
+    Hunk {
+        context_before: to_vec(&search[..prefix]),
+        deletions: to_vec(&search[prefix..search.len() - suffix]),
+        additions: to_vec(&replace[prefix..replace.len() - suffix]),
+        context_after: to_vec(&search[search.len() - suffix..]),
+    }
+}
+
+impl FuDiff {
+    /// Renders this diff as search/replace blocks, one per hunk.
+    ///
+    /// Blocks don't mark where a hunk's context ends, so lines a hunk deletes
+    /// and adds back unchanged parse back as context. After a round trip
+    /// through `parse_search_replace`, such hunks may match elsewhere, or
+    /// drop the trailing newline they kept.
+    pub fn render_search_replace(&self) -> String {
+        let mut output = String::new();
+        for (i, hunk) in self.hunks.iter().enumerate() {
+            if i > 0 {
+                output.push('\n');
+            }
+            output.push_str(SEARCH);
+            output.push('\n');
+            for line in hunk
+                .context_before
+                .iter()
+                .chain(&hunk.deletions)
+                .chain(&hunk.context_after)
+            {
+                output.push_str(shown(line));
+                output.push('\n');
+            }
+            output.push_str(DIVIDER);
+            output.push('\n');
+            for line in hunk
+                .context_before
+                .iter()
+                .chain(&hunk.additions)
+                .chain(&hunk.context_after)
+            {
+                output.push_str(shown(line));
+                output.push('\n');
+            }
+            output.push_str(REPLACE);
+            output.push('\n');
+        }
+        output
+    }
+}
@@ -0,0 +1,252 @@
+//! Side-by-side plain-text rendering for terminals.
+
+use crate::view::{view, Block, Row, Side};
+use crate::{push_visible, FuDiff, Result};
+
+/// Options controlling side-by-side rendering.
+#[derive(Debug, Clone)]
+pub struct SideBySideOptions {
+    /// The total width of each output line, in terminal columns.
+    pub width: usize,
+    /// The number of unchanged lines shown around each hunk. Longer runs of
+    /// unchanged lines are replaced by a line counting them.
+    pub context: usize,
+    /// Columns a tab advances to a multiple of.
+    pub tab_width: usize,
+}
+
+impl Default for SideBySideOptions {
+    fn default() -> Self {
+        SideBySideOptions {
+            width: 80,
+            context: 3,
+            tab_width: 4,
+        }
+    }
+}
+
+impl FuDiff {
+    /// Renders this diff applied to `base` in two columns, like `diff -y`:
+    /// the old text on the left, the new text on the right, each line with
+    /// its one-based line number. Between the columns, `<` marks a deleted
+    /// line, `>` an added line, and `|` a line replaced by the one beside
+    /// it. Lines too long for their column wrap onto continuation lines.
+    /// Widths are measured in terminal columns, so wide characters such as
+    /// CJK ideographs take two. Fails if the diff doesn't apply to `base`.
+    pub fn render_side_by_side(&self, base: &str, opts: &SideBySideOptions) -> Result<String> {
+        let blocks = view(self, base, None)?;
+        let last_line = blocks
+            .iter()
+            .flat_map(|b| match b {
+                Block::Unchanged(rows) | Block::Hunk(rows) => rows,
+            })
+            .flat_map(|r| [&r.old, &r.new])
+            .flatten()
+            .map(|s| s.line + 1)
+            .max()
+            .unwrap_or(1);
+        let number = last_line.to_string().len();
+        // Each side is a line number, a space and the text; the gutter is a
+        // marker with a space on each side.
+        let text = (opts.width.saturating_sub(3) / 2)
+            .saturating_sub(number + 1)
+            .max(1);
+        let layout = Columns {
+            number,
+            text,
+            tab_width: opts.tab_width.max(1),
+        };
+
+        let mut out = String::new();
+        let last = blocks.len().saturating_sub(1);
+        for (i, block) in blocks.iter().enumerate() {
+            match block {
+                Block::Hunk(rows) => rows.iter().for_each(|r| layout.row(&mut out, r)),
+                Block::Unchanged(rows) => {
+                    // Show context after the previous hunk and before the next.
+                    let head = if i > 0 { opts.context } else { 0 };
+                    let tail = if i < last { opts.context } else { 0 };
+                    if head + tail >= rows.len() {
+                        rows.iter().for_each(|r| layout.row(&mut out, r));
+                        continue;
+                    }
+                    rows[..head].iter().for_each(|r| layout.row(&mut out, r));
+                    let skipped = rows.len() - head - tail;
+                    let note = format!(
+                        "⋯ {skipped} unchanged line{} ⋯",
+                        if skipped == 1 { "" } else { "s" }
+                    );
+                    out.push_str(format!("{note:^w$}", w = opts.width).trim_end());
+                    out.push('\n');
+                    rows[rows.len() - tail..]
+                        .iter()
+                        .for_each(|r| layout.row(&mut out, r));
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// The widths of the parts of each column.
+struct Columns {
+    number: usize,
+    text: usize,
+    tab_width: usize,
+}
+
+impl Columns {
+    /// Writes a row, wrapping each side's text to the column width.
+    fn row(&self, out: &mut String, row: &Row) {
+        let marker = match (&row.old, &row.new) {
+            _ if !row.changed => ' ',
+            (Some(_), Some(_)) => '|',
+            (Some(_), None) => '<',
+            _ => '>',
+        };
+        let old = self.wrap(row.old.as_ref());
+        let new = self.wrap(row.new.as_ref());
+        for k in 0..old.len().max(new.len()) {
+            let number = |side: Option<&Side>| match side {
+                Some(s) if k == 0 => format!("{:>w$}", s.line + 1, w = self.number),
+                _ => " ".repeat(self.number),
+            };
+            let blank = || " ".repeat(self.text);
+            let left = old.get(k).cloned().unwrap_or_else(blank);
+            let right = new.get(k).cloned().unwrap_or_else(blank);
+            let marker = if k == 0 { marker } else { ' ' };
+            let line = format!(
+                "{} {} {} {} {}",
+                number(row.old.as_ref()),
+                left,
+                marker,
+                number(row.new.as_ref()),
+                right
+            );
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+    }
+
+    /// Splits a side's text into pieces that each fill the text column,
+    /// padded with spaces. An absent side has no pieces.
+    fn wrap(&self, side: Option<&Side>) -> Vec<String> {
+        let Some(side) = side else {
+            return Vec::new();
+        };
+        // Expand tabs first, so that they line up with the start of the line,
+        // and escape control characters.
+        let mut expanded = String::new();
+        let mut column = 0;
+        for c in side.text.chars() {
+            if c == '\t' {
+                let n = self.tab_width - column % self.tab_width;
+                expanded.extend(std::iter::repeat_n(' ', n));
+                column += n;
+            } else {
+                let from = expanded.len();
+                push_visible(&mut expanded, c);
+                column += expanded[from..].chars().map(width).sum::<usize>();
+            }
+        }
+
+        let mut pieces = Vec::new();
+        let (mut piece, mut used) = (String::new(), 0);
+        for c in expanded.chars() {
+            let w = width(c);
+            // A wide character that doesn't fit moves to the next piece.
+            if used + w > self.text && used > 0 {
+                pieces.push(pad(piece, used, self.text));
+                (piece, used) = (String::new(), 0);
+            }
+            piece.push(c);
+            used += w;
+        }
+        pieces.push(pad(piece, used, self.text));
+        pieces
+    }
+}
+
+/// Pads `piece`, `used` columns wide, with spaces to `width` columns.
+fn pad(mut piece: String, used: usize, width: usize) -> String {
+    piece.extend(std::iter::repeat_n(' ', width.saturating_sub(used)));
+    piece
+}
+
+/// The number of terminal columns `c` takes: none for combining marks and
+/// other zero-width characters, two for wide East Asian characters and most
+/// emoji, and one otherwise.
+fn width(c: char) -> usize {
+    let c = c as u32;
+    match c {
+        0x0300..=0x036F
+        | 0x0483..=0x0489
+        | 0x0591..=0x05BD
+        | 0x0610..=0x061A
+        | 0x064B..=0x065F
+        | 0x0E31
+        | 0x0E34..=0x0E3A
+        | 0x0E47..=0x0E4E
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x200B..=0x200F
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F
+        | 0xFEFF => 0,
+        0x1100..=0x115F
+        | 0x231A..=0x231B
+        | 0x2329..=0x232A
+        | 0x23E9..=0x23EC
+        | 0x23F0
+        | 0x23F3
+        | 0x25FD..=0x25FE
+        | 0x2614..=0x2615
+        | 0x2648..=0x2653
+        | 0x267F
+        | 0x2693
+        | 0x26A1
+        | 0x26AA..=0x26AB
+        | 0x26BD..=0x26BE
+        | 0x26C4..=0x26C5
+        | 0x26CE
+        | 0x26D4
+        | 0x26EA
+        | 0x26F2..=0x26F3
+        | 0x26F5
+        | 0x26FA
+        | 0x26FD
+        | 0x2705
+        | 0x270A..=0x270B
+        | 0x2728
+        | 0x274C
+        | 0x274E
+        | 0x2753..=0x2755
+        | 0x2757
+        | 0x2795..=0x2797
+        | 0x27B0
+        | 0x27BF
+        | 0x2B1B..=0x2B1C
+        | 0x2B50
+        | 0x2B55
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xA960..=0xA97F
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE10..=0xFE19
+        | 0xFE30..=0xFE6F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F680..=0x1F6FF
+        | 0x1F900..=0x1F9FF
+        | 0x1FA70..=0x1FAFF
+        | 0x20000..=0x2FFFD
+        | 0x30000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
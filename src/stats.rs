@@ -0,0 +1,95 @@
+//! Summaries of the changes a diff makes.
+
+use std::fmt::Write;
+
+use crate::{FuDiff, ELISION};
+
+/// Counts of what a diff contains.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Stats {
+    /// The number of hunks.
+    pub hunks: usize,
+    /// Lines added.
+    pub added: usize,
+    /// Lines removed.
+    pub removed: usize,
+    /// Lines of context, not counting elision markers.
+    pub context: usize,
+}
+
+impl FuDiff {
+    /// Counts the hunks and lines of this diff.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            hunks: self.hunks.len(),
+            ..Default::default()
+        };
+        for hunk in &self.hunks {
+            stats.added += hunk.additions.len();
+            stats.removed += hunk.deletions.len();
+            stats.context += hunk
+                .context_before
+                .iter()
+                .chain(&hunk.context_after)
+                .filter(|l| *l != ELISION)
+                .count();
+        }
+        stats
+    }
+}
+
+/// Renders a summary of changes to several files in the style of
+/// `git diff --stat`: a line per file with its path, the number of lines
+/// changed, and a bar of `+` and `-` scaled to fit in `width` columns,
+/// followed by a line of totals.
+pub fn diffstat(files: &[(&str, &FuDiff)], width: usize) -> String {
+    let stats: Vec<Stats> = files.iter().map(|(_, d)| d.stats()).collect();
+    let name_width = files
+        .iter()
+        .map(|(p, _)| p.chars().count())
+        .max()
+        .unwrap_or(0);
+    let largest = stats.iter().map(|s| s.added + s.removed).max().unwrap_or(0);
+    let count_width = largest.to_string().len();
+    // " name | count bar"
+    let room = width.saturating_sub(name_width + count_width + 5).max(1);
+
+    let mut out = String::new();
+    for ((path, _), s) in files.iter().zip(&stats) {
+        let changed = s.added + s.removed;
+        let (mut plus, mut minus) = (s.added, s.removed);
+        if largest > room {
+            // Scale down, keeping at least one mark for any change.
+            let scale = |n: usize| {
+                if n == 0 {
+                    0
+                } else {
+                    (n * room / largest).max(1)
+                }
+            };
+            (plus, minus) = (scale(s.added), scale(s.removed));
+        }
+        let line = format!(
+            " {path:<name_width$} | {changed:>count_width$} {}{}",
+            "+".repeat(plus),
+            "-".repeat(minus)
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+
+    let added: usize = stats.iter().map(|s| s.added).sum();
+    let removed: usize = stats.iter().map(|s| s.removed).sum();
+    let plural =
+        |n: usize, one: &str, many: &str| format!("{n} {}", if n == 1 { one } else { many });
+    let mut total = format!(" {}", plural(files.len(), "file changed", "files changed"));
+    if added > 0 || removed == 0 {
+        let _ = write!(total, ", {}(+)", plural(added, "insertion", "insertions"));
+    }
+    if removed > 0 || added == 0 {
+        let _ = write!(total, ", {}(-)", plural(removed, "deletion", "deletions"));
+    }
+    out.push_str(&total);
+    out.push('\n');
+    out
+}
@@ -192,6 +192,12 @@ fn test_revert() {
             }
         }
     }
+
+    // The trailing newline a patch removed isn't recorded, so reverting
+    // keeps the input's.
+    let diff = crate::diff("b\n", "");
+    assert_eq!(diff.patch("b\n").unwrap(), "");
+    assert_eq!(diff.revert("").unwrap(), "b");
 }
 
 #[test]
@@ -382,6 +388,21 @@ fn test_parse_render_round_trip() {
             input, rendered
         );
     }
+
+    // Elisions render as `...`, which only parse back as elisions when
+    // parsing is told to recognize them.
+    let opts = crate::ParseOptions {
+        elisions: vec!["...".to_string()],
+        ..Default::default()
+    };
+    let elided = crate::parse_with("@@ @@\n a\n ...\n-b\n", &opts).unwrap();
+    let rendered = elided.render();
+    assert_eq!(rendered, "@@ @@\n a\n ...\n-b\n");
+    assert_eq!(crate::parse_with(&rendered, &opts).unwrap(), elided);
+    assert_eq!(
+        crate::parse(&rendered).unwrap().hunks[0].context_before,
+        vec!["a".to_string(), "...".to_string()]
+    );
 }
 
 #[test]
@@ -577,3 +598,1989 @@ fn test_deletions_only() {
         assert_eq!(reverted, original);
     }
 }
+
+#[test]
+fn test_patch_unordered() {
+    let opts = PatchOptions {
+        unordered: true,
+        ..Default::default()
+    };
+    let test_cases = vec![
+        // Hunks in file order still apply.
+        (
+            "a\nb\nc\nd\ne",
+            "@@ @@\n a\n-b\n+x\n@@ @@\n d\n-e\n+y\n",
+            Ok("a\nx\nc\nd\ny"),
+        ),
+        // Hunks in reverse order.
+        (
+            "a\nb\nc\nd\ne",
+            "@@ @@\n d\n-e\n+y\n@@ @@\n a\n-b\n+x\n",
+            Ok("a\nx\nc\nd\ny"),
+        ),
+        // Hunks without leading context are found by their deletions.
+        (
+            "a\nb\nc\nd\n",
+            "@@ @@\n-d\n+z\n@@ @@\n-a\n+w\n",
+            Ok("w\nb\nc\nz\n"),
+        ),
+        // Overlapping hunks are rejected.
+        ("a\nb\nc", "@@ @@\n a\n-b\n@@ @@\n b\n-c\n", Err("overlaps")),
+        // Insertions at the same point keep the order of the diff.
+        (
+            "a\nb\nc",
+            "@@ @@\n a\n+x\n@@ @@\n a\n+y\n",
+            Ok("a\nx\ny\nb\nc"),
+        ),
+        // Duplicate context is ambiguous across the whole input.
+        (
+            "a\nb\na\nb",
+            "@@ @@\n a\n-b\n+x\n@@ @@\n a\n-b\n+y\n",
+            Err("Multiple matches for context"),
+        ),
+        // So are hunks without leading context, even where they fit at the
+        // start of the input.
+        (
+            "a\nb\na\nb\n",
+            "@@ @@\n-a\n+z\n b\n",
+            Err("Multiple matches for deletions and context"),
+        ),
+        // A hunk without any context or deletions inserts at the start, as
+        // in ordered mode.
+        ("a\nb\n", "@@ @@\n+x\n", Ok("x\na\nb\n")),
+    ];
+
+    for (input, diff_str, expected) in test_cases {
+        let diff = crate::parse(diff_str).unwrap();
+        match (diff.patch_with(input, &opts), expected) {
+            (Ok(result), Ok(expected)) => assert_eq!(result, expected),
+            (Err(Error::Apply { details: msg, .. }), Err(expected_msg))
+            | (Err(Error::AmbiguousMatch { details: msg, .. }), Err(expected_msg))
+            | (Err(Error::OverlappingHunks { details: msg, .. }), Err(expected_msg)) => {
+                assert!(msg.contains(expected_msg), "{msg}");
+            }
+            (result, expected) => {
+                panic!("Unexpected result: {:?}, expected: {:?}", result, expected);
+            }
+        }
+    }
+
+    // The default ordered mode can't find context above the previous hunk.
+    let diff = crate::parse("@@ @@\n d\n-e\n+y\n@@ @@\n a\n-b\n+x\n").unwrap();
+    assert!(diff.patch("a\nb\nc\nd\ne").is_err());
+}
+
+#[test]
+fn test_overlapping_hunks() {
+    let test_cases = vec![
+        // A hunk's context is deleted by the previous hunk.
+        ("a\nb\nc\nd", "@@ @@\n a\n-b\n c\n@@ @@\n c\n-d\n", true),
+        // A hunk deletes a line the previous hunk's trailing context relies on.
+        (
+            "a\nb\nc\nd",
+            "@@ @@\n a\n-b\n c\n d\n@@ @@\n c\n-d\n",
+            false,
+        ),
+        // Shared context between hunks is fine.
+        (
+            "a\nb\nc\nd\ne",
+            "@@ @@\n a\n-b\n+x\n c\n@@ @@\n c\n-d\n+y\n e\n",
+            true,
+        ),
+    ];
+
+    for (input, diff_str, ok) in test_cases {
+        let diff = crate::parse(diff_str).unwrap();
+        match diff.patch(input) {
+            Ok(_) => assert!(ok, "expected overlap for {diff_str:?}"),
+            Err(Error::OverlappingHunks { details, .. }) => {
+                assert!(!ok, "unexpected overlap for {diff_str:?}");
+                assert!(details.contains("Hunk 1"), "{details}");
+            }
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+}
+
+#[test]
+fn test_normalize() {
+    let test_cases = vec![
+        // Hunks sharing context are merged.
+        (
+            "a\nb\nc\nd\ne",
+            "@@ @@\n a\n-b\n+x\n c\n@@ @@\n c\n-d\n+y\n e\n",
+            vec![Hunk {
+                context_before: vec!["a".to_string()],
+                deletions: vec!["b".to_string(), "c".to_string(), "d".to_string()],
+                additions: vec!["x".to_string(), "c".to_string(), "y".to_string()],
+                context_after: vec!["e".to_string()],
+            }],
+        ),
+        // Adjacent hunks are merged.
+        (
+            "a\nb\nc\nd",
+            "@@ @@\n a\n-b\n@@ @@\n c\n+x\n",
+            vec![Hunk {
+                context_before: vec!["a".to_string()],
+                deletions: vec!["b".to_string(), "c".to_string()],
+                additions: vec!["c".to_string(), "x".to_string()],
+                context_after: vec![],
+            }],
+        ),
+        // Separate hunks are kept apart and put in file order.
+        (
+            "a\nb\nc\nd\ne",
+            "@@ @@\n d\n-e\n@@ @@\n a\n-b\n",
+            vec![
+                Hunk {
+                    context_before: vec!["a".to_string()],
+                    deletions: vec!["b".to_string()],
+                    additions: vec![],
+                    context_after: vec![],
+                },
+                Hunk {
+                    context_before: vec!["d".to_string()],
+                    deletions: vec!["e".to_string()],
+                    additions: vec![],
+                    context_after: vec![],
+                },
+            ],
+        ),
+    ];
+
+    let opts = PatchOptions {
+        unordered: true,
+        ..Default::default()
+    };
+    for (input, diff_str, expected_hunks) in test_cases {
+        let diff = crate::parse(diff_str).unwrap();
+        let normalized = diff.normalize(input, &opts).unwrap();
+        assert_eq!(normalized.hunks, expected_hunks);
+        assert_eq!(
+            normalized.patch(input).unwrap(),
+            diff.patch_with(input, &opts).unwrap()
+        );
+    }
+
+    let diff = crate::parse("@@ @@\n a\n-b\n@@ @@\n b\n-c\n").unwrap();
+    assert!(matches!(
+        diff.normalize("a\nb\nc", &opts),
+        Err(Error::OverlappingHunks { .. })
+    ));
+}
+
+#[test]
+fn test_validate() {
+    let test_cases = vec![
+        ("@@ @@\n a\n-b\n+c\n", Ok(())),
+        // Context-only hunks are no-op assertions.
+        ("@@ @@\n a\n b\n", Ok(())),
+        // A lone hunk without context applies to the whole file.
+        ("@@ @@\n+a\n", Ok(())),
+        ("@@ @@\n-a\n", Ok(())),
+        // Trailing context anchors an insertion.
+        ("@@ @@\n a\n-b\n@@ @@\n+x\n c\n", Ok(())),
+        (
+            "@@ @@\n a\n-b\n@@ @@\n+x\n",
+            Err("Hunk 2 adds lines without any context"),
+        ),
+        (
+            "@@ @@\n-x\n@@ @@\n a\n-b\n",
+            Err("Hunk 1 deletes lines without any context"),
+        ),
+        ("@@ @@\n a\n-b\n@@ @@\n", Err("Hunk 2 is empty")),
+    ];
+
+    for (diff_str, expected) in test_cases {
+        let diff = crate::parse(diff_str).unwrap();
+        match (diff.validate(), expected) {
+            (Ok(()), Ok(())) => {}
+            (Err(Error::InvalidHunk { details, .. }), Err(expected_msg)) => {
+                assert!(details.contains(expected_msg), "{details}");
+            }
+            (result, expected) => {
+                panic!("Unexpected result: {:?}, expected: {:?}", result, expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_strict() {
+    let opts = ParseOptions {
+        mode: ParseMode::Strict,
+        ..Default::default()
+    };
+    let test_cases = vec![
+        ("@@ @@\n a\n-b\n+c\n d\n", Ok(())),
+        ("@@ @@\n a\n-b\n+c\n d\n\n\n", Ok(())),
+        ("@@ @@\n a\nb\n-c\n", Err("Line 3: Line has no diff marker")),
+        (
+            "@@ @@\n a\n\n-c\n",
+            Err("Line 3: Empty line has no diff marker"),
+        ),
+        ("@@ @@\n a\n-b\n c\n-d\n", Err("Line 5: Change follows")),
+        ("@@ @@\n a\n-b\n@@ @@\n+x\n", Err("Hunk 2 adds lines")),
+        (
+            "@@ @@\n é\nébc\n-x\n",
+            Err("Line 3: Line has no diff marker"),
+        ),
+    ];
+
+    for (diff_str, expected) in test_cases {
+        match (crate::parse_with(diff_str, &opts), expected) {
+            (Ok(diff), Ok(())) => assert_eq!(diff, crate::parse(diff_str).unwrap()),
+            (Err(Error::Parse { details, .. }), Err(expected_msg))
+            | (Err(Error::InvalidHunk { details, .. }), Err(expected_msg)) => {
+                assert!(details.contains(expected_msg), "{details}");
+            }
+            (result, expected) => {
+                panic!("Unexpected result: {:?}, expected: {:?}", result, expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_patch_anchors() {
+    let test_cases = vec![
+        // Context-only hunks assert that their context is present.
+        ("a\nb\nc", "@@ @@\n-a\n+x\n@@ @@\n b\n c\n", Ok("x\nb\nc")),
+        ("a\nb\nc", "@@ @@\n b\n d\n", Err("Could not find context")),
+        // Trailing context is checked after the deletions.
+        (
+            "a\nb\nc",
+            "@@ @@\n a\n-b\n+x\n d\n",
+            Err("Context mismatch at line 3 - expected 'd', found 'c'"),
+        ),
+        (
+            "a\nb",
+            "@@ @@\n a\n-b\n c\n",
+            Err("Context extends past end of file"),
+        ),
+        // An insertion anchored only by trailing context.
+        ("a\nb\nc", "@@ @@\n+x\n c\n", Ok("a\nb\nx\nc")),
+        ("a\nb\nc", "@@ @@\n+x\n a\n", Ok("x\na\nb\nc")),
+    ];
+
+    for (input, diff_str, expected) in test_cases {
+        let diff = crate::parse(diff_str).unwrap();
+        match (diff.patch(input), expected) {
+            (Ok(result), Ok(expected)) => assert_eq!(result, expected),
+            (Err(Error::Apply { details: msg, .. }), Err(expected_msg)) => {
+                assert!(msg.contains(expected_msg), "{msg}");
+            }
+            (result, expected) => {
+                panic!("Unexpected result: {:?}, expected: {:?}", result, expected);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_check() {
+    let input = "a\nb\nc\nd\ne\nf\na";
+    let diff = crate::parse(
+        "@@ @@\n a\n-x\n@@ @@\n missing\n-c\n@@ @@\n c\n-d\n e\n@@ @@\n d\n-e\n@@ @@\n f\n-a\n+z\n",
+    )
+    .unwrap();
+    let kinds: Vec<(usize, DiagnosticKind)> = diff
+        .check_with(
+            input,
+            &PatchOptions {
+                unordered: true,
+                ..Default::default()
+            },
+        )
+        .into_iter()
+        .map(|d| (d.hunk, d.kind))
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (
+                0,
+                DiagnosticKind::Ambiguous {
+                    candidates: vec![0, 6]
+                }
+            ),
+            (1, DiagnosticKind::NotFound),
+            (3, DiagnosticKind::Overlap { other: 2 }),
+        ]
+    );
+
+    let diff = crate::parse("@@ @@\n a\n-x\n@@ @@\n e\n f\n g\n").unwrap();
+    let kinds: Vec<DiagnosticKind> = diff.check(input).into_iter().map(|d| d.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            DiagnosticKind::Ambiguous {
+                candidates: vec![0, 6]
+            },
+            DiagnosticKind::NotFound,
+        ]
+    );
+
+    let diff = crate::parse("@@ @@\n b\n-x\n@@ @@\n e\n-f\n-a\n-q\n").unwrap();
+    let kinds: Vec<DiagnosticKind> = diff.check(input).into_iter().map(|d| d.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            DiagnosticKind::DeletionMismatch {
+                line: 2,
+                expected: "x".to_string(),
+                found: "c".to_string()
+            },
+            DiagnosticKind::DeletionPastEnd,
+        ]
+    );
+
+    // Hunks with an invalid shape are reported before location problems.
+    let diff = crate::parse("@@ @@\n-b\n@@ @@\n@@ @@\n q\n-c\n").unwrap();
+    let found: Vec<(usize, DiagnosticKind, String)> = diff
+        .check(input)
+        .into_iter()
+        .map(|d| (d.hunk, d.kind, d.message))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (
+                0,
+                DiagnosticKind::InvalidShape,
+                "deletes lines without any context to anchor them".to_string()
+            ),
+            (1, DiagnosticKind::InvalidShape, "is empty".to_string()),
+            (
+                2,
+                DiagnosticKind::NotFound,
+                "Could not find context: [\"q\"]".to_string()
+            ),
+        ]
+    );
+    let err: Error = diff.check(input).swap_remove(0).into();
+    assert_eq!(err.details(), diff.validate().unwrap_err().details());
+
+    // A diff that applies cleanly has no diagnostics.
+    let diff = crate::diff(input, "a\nb\nX\nd\ne\nf\na");
+    assert!(diff.check(input).is_empty());
+}
+
+#[test]
+fn test_patch_repair() {
+    let input = "fn main() {\n    let x = 1;\n    let y = 2;\n    println!(\"{}\", x + y);\n}\n";
+    let test_cases = vec![
+        // A context line that is slightly off stands in for the real line.
+        (
+            "@@ @@\n fn main() {\n     let x = 10;\n-    let y = 2;\n+    let y = 3;\n",
+            Ok((
+                "fn main() {\n    let x = 1;\n    let y = 3;\n    println!(\"{}\", x + y);\n}\n",
+                vec![Repair {
+                    hunk: 0,
+                    context: "    let x = 10;".to_string(),
+                    line: Some(1),
+                }],
+            )),
+        ),
+        // An invented context line is dropped.
+        (
+            "@@ @@\n fn main() {\n     // setup\n     let x = 1;\n-    let y = 2;\n     println!(\"{}\", x + y);\n",
+            Ok((
+                "fn main() {\n    let x = 1;\n    println!(\"{}\", x + y);\n}\n",
+                vec![Repair {
+                    hunk: 0,
+                    context: "    // setup".to_string(),
+                    line: None,
+                }],
+            )),
+        ),
+        // Trailing context is repaired too.
+        (
+            "@@ @@\n     let x = 1;\n-    let y = 2;\n     println!(\"{}\", y);\n }\n",
+            Ok((
+                "fn main() {\n    let x = 1;\n    println!(\"{}\", x + y);\n}\n",
+                vec![Repair {
+                    hunk: 0,
+                    context: "    println!(\"{}\", y);".to_string(),
+                    line: Some(3),
+                }],
+            )),
+        ),
+        // Too many bad lines for the budget.
+        (
+            "@@ @@\n fn start() {\n     let x = 10;\n-    let y = 2;\n",
+            Err("Could not find context"),
+        ),
+        // Deletions must still match.
+        (
+            "@@ @@\n fn main() {\n     let x = 10;\n-    let y = 20;\n",
+            Err("Could not find context"),
+        ),
+    ];
+
+    let opts = PatchOptions {
+        repair: 1,
+        ..Default::default()
+    };
+    for (diff_str, expected) in test_cases {
+        let diff = crate::parse(diff_str).unwrap();
+        match (diff.patch_report(input, &opts), expected) {
+            (Ok(patched), Ok((output, repairs))) => {
+                assert_eq!(patched.output, output);
+                assert_eq!(patched.repairs, repairs);
+            }
+            (Err(Error::Apply { details, .. }), Err(expected_msg)) => {
+                assert!(details.contains(expected_msg), "{details}");
+            }
+            (result, expected) => {
+                panic!("Unexpected result: {:?}, expected: {:?}", result, expected);
+            }
+        }
+    }
+
+    // Without repair the same diff fails.
+    let diff =
+        crate::parse("@@ @@\n fn main() {\n     let x = 10;\n-    let y = 2;\n+    let y = 3;\n")
+            .unwrap();
+    assert!(diff.patch(input).is_err());
+}
+
+#[test]
+fn test_patch_indent() {
+    let input = "impl Foo {\n    fn bar(&self) {\n        let x = 1;\n        x\n    }\n}\n";
+    let tabbed = "impl Foo {\n\tfn bar(&self) {\n\t\tlet x = 1;\n\t\tx\n\t}\n}\n";
+    let test_cases = vec![
+        // A method quoted at column zero is matched and its additions shifted.
+        (
+            input,
+            "@@ @@\n fn bar(&self) {\n-    let x = 1;\n+    let x = 2;\n+    if x > 1 {\n+        println!(\"big\");\n+    }\n     x\n",
+            Ok("impl Foo {\n    fn bar(&self) {\n        let x = 2;\n        if x > 1 {\n            println!(\"big\");\n        }\n        x\n    }\n}\n"),
+        ),
+        // Over-indented hunks are shifted left.
+        (
+            input,
+            "@@ @@\n             let x = 1;\n-            x\n+            x + 1\n",
+            Ok("impl Foo {\n    fn bar(&self) {\n        let x = 1;\n        x + 1\n    }\n}\n"),
+        ),
+        // Space-indented hunks are matched against tab-indented input, and
+        // their additions written with tabs.
+        (
+            tabbed,
+            "@@ @@\n fn bar(&self) {\n-    let x = 1;\n+    let x = 2;\n",
+            Ok("impl Foo {\n\tfn bar(&self) {\n\t\tlet x = 2;\n\t\tx\n\t}\n}\n"),
+        ),
+        // Every line must share the same shift.
+        (
+            input,
+            "@@ @@\n fn bar(&self) {\n-let x = 1;\n+let x = 2;\n",
+            Err("Could not find context"),
+        ),
+        // Text must still match exactly after the indentation.
+        (
+            input,
+            "@@ @@\n fn bar(&self) {\n-    let x = 3;\n",
+            Err("Could not find context"),
+        ),
+    ];
+
+    let opts = PatchOptions {
+        matching: Matching::Indent,
+        ..Default::default()
+    };
+    for (input, diff_str, expected) in test_cases {
+        let diff = crate::parse(diff_str).unwrap();
+        match (diff.patch_with(input, &opts), expected) {
+            (Ok(result), Ok(expected)) => assert_eq!(result, expected),
+            (Err(Error::Apply { details, .. }), Err(expected_msg)) => {
+                assert!(details.contains(expected_msg), "{details}");
+            }
+            (result, expected) => {
+                panic!("Unexpected result: {:?}, expected: {:?}", result, expected);
+            }
+        }
+    }
+
+    // Exact matches are preferred, and leave additions untouched.
+    let diff = crate::parse("@@ @@\n     fn bar(&self) {\n+  // odd\n").unwrap();
+    assert_eq!(
+        diff.patch_with(input, &opts).unwrap(),
+        "impl Foo {\n    fn bar(&self) {\n  // odd\n        let x = 1;\n        x\n    }\n}\n"
+    );
+
+    // Repair combines with indent-relative matching.
+    let opts = PatchOptions {
+        matching: Matching::Indent,
+        repair: 1,
+        ..Default::default()
+    };
+    let diff = crate::parse("@@ @@\n fn baz(&self) {\n-    let x = 1;\n+    let x = 2;\n").unwrap();
+    let patched = diff.patch_report(input, &opts).unwrap();
+    assert_eq!(
+        patched.output,
+        "impl Foo {\n    fn bar(&self) {\n        let x = 2;\n        x\n    }\n}\n"
+    );
+    assert_eq!(patched.repairs[0].line, Some(1));
+}
+
+#[test]
+fn test_patch_elision() {
+    let input = "fn main() {\n    let a = 1;\n    let b = 2;\n    let c = 3;\n    println!(\"{}\", a + b + c);\n}\n\nfn other() {\n    println!(\"{}\", a + b + c);\n}\n";
+    let opts = ParseOptions {
+        elisions: vec!["// ... existing code ...".to_string(), "...".to_string()],
+        ..Default::default()
+    };
+    let test_cases = vec![
+        // An elision between leading context runs.
+        (
+            "@@ @@\n fn main() {\n // ... existing code ...\n-    println!(\"{}\", a + b + c);\n+    println!(\"{}\", a * b * c);\n }\n",
+            Ok("fn main() {\n    let a = 1;\n    let b = 2;\n    let c = 3;\n    println!(\"{}\", a * b * c);\n}\n\nfn other() {\n    println!(\"{}\", a + b + c);\n}\n"),
+        ),
+        // Unprefixed markers in trailing context.
+        (
+            "@@ @@\n fn main() {\n-    let a = 1;\n+    let a = 10;\n    // ... existing code ...\n }\n",
+            Ok("fn main() {\n    let a = 10;\n    let b = 2;\n    let c = 3;\n    println!(\"{}\", a + b + c);\n}\n\nfn other() {\n    println!(\"{}\", a + b + c);\n}\n"),
+        ),
+        // The runs around a gap must each be found.
+        (
+            "@@ @@\n fn missing() {\n ...\n-    println!(\"{}\", a + b + c);\n",
+            Err("Could not find context"),
+        ),
+        // An elision between two changes splits them into separate hunks.
+        (
+            "@@ @@\n fn main() {\n-    let a = 1;\n+    let a = 10;\n // ... existing code ...\n-    println!(\"{}\", a + b + c);\n+    println!(\"{}\", a * b * c);\n }\n",
+            Ok("fn main() {\n    let a = 10;\n    let b = 2;\n    let c = 3;\n    println!(\"{}\", a * b * c);\n}\n\nfn other() {\n    println!(\"{}\", a + b + c);\n}\n"),
+        ),
+        // Without the outer run the change is ambiguous.
+        (
+            "@@ @@\n ...\n-    println!(\"{}\", a + b + c);\n }\n",
+            Err("Multiple matches"),
+        ),
+    ];
+
+    for (diff_str, expected) in test_cases {
+        let diff = crate::parse_with(diff_str, &opts).unwrap();
+        assert!(diff
+            .hunks
+            .iter()
+            .flat_map(|h| h.context_before.iter().chain(&h.context_after))
+            .any(|l| l == ELISION));
+        match (diff.patch(input), expected) {
+            (Ok(result), Ok(expected)) => assert_eq!(result, expected),
+            (Err(Error::Apply { details, .. }), Err(expected_msg))
+            | (Err(Error::AmbiguousMatch { details, .. }), Err(expected_msg)) => {
+                assert!(details.contains(expected_msg), "{details}");
+            }
+            (result, expected) => {
+                panic!("Unexpected result: {:?}, expected: {:?}", result, expected);
+            }
+        }
+    }
+
+    let diff = crate::parse_with(
+        "@@ @@\n a\n-b\n ...\n c\n-d\n+D\n",
+        &ParseOptions {
+            mode: ParseMode::Strict,
+            ..opts.clone()
+        },
+    )
+    .unwrap();
+    assert_eq!(diff.hunks.len(), 2);
+    assert_eq!(diff.hunks[1].context_before, vec![ELISION, "c"]);
+    assert_eq!(diff.patch("a\nb\nx\nc\nd\n").unwrap(), "a\nx\nc\nD\n");
+
+    // Literal "..." lines still match literally.
+    let diff = crate::parse("@@ @@\n a\n ...\n-b\n+c\n").unwrap();
+    assert_eq!(diff.patch("x\na\n...\nb").unwrap(), "x\na\n...\nc");
+    // They are never treated as gaps.
+    let diff = crate::parse("@@ @@\n a\n ...\n-d\n").unwrap();
+    assert!(diff.patch("a\nb\nc\nd\n").is_err());
+
+    // Elisions are rendered as "...", which parses back as an elision when
+    // configured.
+    let diff = crate::parse_with("@@ @@\n a\n // ... existing code ...\n-d\n", &opts).unwrap();
+    assert_eq!(diff.render(), "@@ @@\n a\n ...\n-d\n");
+    assert_eq!(crate::parse_with(&diff.render(), &opts).unwrap(), diff);
+
+    // Markers are only recognized when configured.
+    let diff = crate::parse("@@ @@\n // ... existing code ...\n-b\n").unwrap();
+    assert_eq!(
+        diff.hunks[0].context_before,
+        vec!["// ... existing code ..."]
+    );
+}
+
+#[test]
+fn test_extract() {
+    let response = "Here's the fix for `src/main.rs`:\n\n```diff\n@@ @@\n fn main() {\n-    old();\n+    new();\n }\n```\n\nAnd the config:\n\n```diff Cargo.toml\n@@ @@\n [package]\n-version = \"0.1.0\"\n+version = \"0.2.0\"\n```\n\nThis also needs:\n\n--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,2 +1,2 @@\n-pub mod a;\n+pub mod b;\n\n pub mod c;\n--- a/README.md\n+++ b/README.md\n@@ @@\n-Old\n+New\nThat's all. Here is some rust:\n\n```rust\nfn main() {}\n```\n";
+    let found = crate::extract(response);
+    let summary: Vec<(Option<&str>, &str)> = found
+        .iter()
+        .map(|e| (e.path.as_deref(), &response[e.span.clone()]))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (
+                Some("src/main.rs"),
+                "@@ @@\n fn main() {\n-    old();\n+    new();\n }"
+            ),
+            (
+                Some("Cargo.toml"),
+                "@@ @@\n [package]\n-version = \"0.1.0\"\n+version = \"0.2.0\""
+            ),
+            (
+                Some("src/lib.rs"),
+                "--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,2 +1,2 @@\n-pub mod a;\n+pub mod b;\n\n pub mod c;"
+            ),
+            (
+                Some("README.md"),
+                "--- a/README.md\n+++ b/README.md\n@@ @@\n-Old\n+New"
+            ),
+        ]
+    );
+    let diff = found[0].diff.as_ref().unwrap();
+    assert_eq!(
+        diff.patch("fn main() {\n    old();\n}\n").unwrap(),
+        "fn main() {\n    new();\n}\n"
+    );
+    assert_eq!(
+        found[2].diff.as_ref().unwrap().hunks[0].context_after,
+        vec!["pub mod c;"]
+    );
+
+    // Several files in one fence, with git headers and CRLF line endings.
+    let response = "```diff\r\ndiff --git a/x.txt b/x.txt\r\nindex 123..456 100644\r\n--- a/x.txt\r\n+++ b/x.txt\r\n@@ @@\r\n-a\r\n+b\r\ndiff --git a/y.txt b/y.txt\r\nnew file mode 100644\r\n--- /dev/null\r\n+++ b/y.txt\r\n@@ @@\r\n+c\r\n```\r\n";
+    let found = crate::extract(response);
+    let paths: Vec<_> = found.iter().map(|e| e.path.as_deref()).collect();
+    assert_eq!(paths, vec![Some("x.txt"), Some("y.txt")]);
+    assert!(response[found[0].span.clone()].starts_with("--- a/x.txt"));
+    assert_eq!(
+        found[1].diff.as_ref().unwrap().hunks[0].additions,
+        vec!["c"]
+    );
+
+    // A block that fails to parse is reported with its error.
+    let found = crate::extract_with(
+        "```diff\n@@ @@\n context\nprose\n+added\n```",
+        &ParseOptions {
+            mode: ParseMode::Strict,
+            ..Default::default()
+        },
+    );
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].path, None);
+    assert!(matches!(found[0].diff, Err(Error::Parse { .. })));
+
+    // Lines starting with a multi-byte character are parsed as context.
+    let found = crate::extract("```diff\n@@ @@\n é\nébc\n-x\n```\n");
+    assert_eq!(
+        found[0].diff.as_ref().unwrap().hunks[0].context_before,
+        vec!["é", "ébc"]
+    );
+
+    // Text without diffs yields nothing.
+    assert!(crate::extract("No changes needed.\n```\nls -la\n```\n").is_empty());
+}
+
+#[test]
+fn test_parse_lenient() {
+    use crate::ParseWarningKind::*;
+    let lenient = ParseOptions {
+        mode: ParseMode::Lenient,
+        ..Default::default()
+    };
+    let strict = ParseOptions {
+        mode: ParseMode::Strict,
+        ..Default::default()
+    };
+    let clean = "@@ @@\n a\n-b\n+c\n d\n";
+    let test_cases = vec![
+        ("@@ @@\na\n-b\n+c\n d\n", vec![MissingSpace]),
+        ("@@ @@\n a\n\t-b\n\t+c\n d\n", vec![IndentedMarker, IndentedMarker]),
+        ("@@ -1,3 +1,3 @@ fn main()\n a\n-b\n+c\n d\n", vec![HeaderText]),
+        ("@@ @@\r\n a\r\n-b\r\n+c\r\n d\r\n", vec![CrLf]),
+        ("```diff\n@@ @@\n a\n-b\n+c\n d\n```\n", vec![Fence, Fence]),
+        (
+            "Here is the change:\n\n@@ @@\n a\n-b\n+c\n d\n\nThis replaces b with c.\nIt is safe.\n",
+            vec![LeadingText, TrailingText],
+        ),
+        (
+            "```diff\n@@ @@\n a\n-b\n+c\n d\n```\n\nThis replaces b with c.\n",
+            vec![Fence, Fence, TrailingText],
+        ),
+        // Trailing text ends the hunk even when it contains a bulleted list.
+        (
+            "@@ @@\n a\n-b\n+c\n d\n\nThis change:\n- replaces b with c\n",
+            vec![TrailingText],
+        ),
+    ];
+
+    for (diff_str, kinds) in test_cases {
+        let parsed = crate::parse_report(diff_str, &lenient).unwrap();
+        assert_eq!(parsed.diff, crate::parse(clean).unwrap(), "{diff_str:?}");
+        let found: Vec<_> = parsed.warnings.iter().map(|w| w.kind).collect();
+        assert_eq!(found, kinds, "{diff_str:?}");
+        assert!(
+            crate::parse_with(diff_str, &strict).is_err(),
+            "strict accepted {diff_str:?}"
+        );
+    }
+
+    // Well-formed diffs, including unified range headers, parse without
+    // warnings in every mode.
+    for diff_str in [clean, "@@ -1,3 +1,3 @@\n a\n-b\n+c\n d\n"] {
+        assert!(crate::parse_report(diff_str, &lenient)
+            .unwrap()
+            .warnings
+            .is_empty());
+        assert!(crate::parse_with(diff_str, &strict).is_ok());
+    }
+
+    // Empty lines inside a hunk are blank context; between hunks they are
+    // separators.
+    let parsed = crate::parse_report("@@ @@\n a\n\n-b\n\n@@ @@\n-c\n", &lenient).unwrap();
+    assert_eq!(parsed.diff.hunks[0].context_before, vec!["a", ""]);
+    assert!(parsed.diff.hunks[0].context_after.is_empty());
+    assert_eq!(parsed.warnings.len(), 1);
+    assert_eq!(parsed.warnings[0].line, 2);
+    assert_eq!(parsed.diff.patch("a\n\nb\nc").unwrap(), "a\n");
+
+    // Lines starting with a multi-byte character have no diff marker.
+    let parsed = crate::parse_report("@@ @@\n é\nébc\n-x\n", &lenient).unwrap();
+    assert_eq!(parsed.diff.hunks[0].context_before, vec!["é", "ébc"]);
+    assert_eq!(parsed.warnings[0].kind, MissingSpace);
+}
+
+#[test]
+fn test_search_replace() {
+    let input = "fn main() {\n    let x = 1;\n    old(x);\n}\n";
+    let test_cases = vec![
+        (
+            "Update `src/main.rs`:\n\n<<<<<<< SEARCH\n    let x = 1;\n    old(x);\n}\n=======\n    let x = 1;\n    new(x);\n    done();\n}\n>>>>>>> REPLACE\n",
+            vec![Hunk {
+                context_before: vec!["    let x = 1;".to_string()],
+                deletions: vec!["    old(x);".to_string()],
+                additions: vec!["    new(x);".to_string(), "    done();".to_string()],
+                context_after: vec!["}".to_string()],
+            }],
+            Ok("fn main() {\n    let x = 1;\n    new(x);\n    done();\n}\n"),
+        ),
+        // Several blocks, one of them deleting lines outright.
+        (
+            "<<<<<<< SEARCH\nfn main() {\n=======\nfn start() {\n>>>>>>> REPLACE\n\n<<<<<<< SEARCH\n    let x = 1;\n    old(x);\n=======\n    old(x);\n>>>>>>> REPLACE\n",
+            vec![
+                Hunk {
+                    context_before: vec![],
+                    deletions: vec!["fn main() {".to_string()],
+                    additions: vec!["fn start() {".to_string()],
+                    context_after: vec![],
+                },
+                Hunk {
+                    context_before: vec![],
+                    deletions: vec!["    let x = 1;".to_string()],
+                    additions: vec![],
+                    context_after: vec!["    old(x);".to_string()],
+                },
+            ],
+            Ok("fn start() {\n    old(x);\n}\n"),
+        ),
+        // A search that isn't in the input fails like any other hunk.
+        (
+            "<<<<<<< SEARCH\n    missing();\n=======\n    found();\n>>>>>>> REPLACE\n",
+            vec![Hunk {
+                context_before: vec![],
+                deletions: vec!["    missing();".to_string()],
+                additions: vec!["    found();".to_string()],
+                context_after: vec![],
+            }],
+            Err("Deletion mismatch"),
+        ),
+    ];
+
+    for (blocks, hunks, expected) in test_cases {
+        let diff = crate::parse_search_replace(blocks).unwrap();
+        assert_eq!(diff.hunks, hunks);
+        match (diff.patch(input), expected) {
+            (Ok(result), Ok(expected)) => assert_eq!(result, expected),
+            (Err(Error::Apply { details, .. }), Err(expected_msg)) => {
+                assert!(details.contains(expected_msg), "{details}");
+            }
+            (result, expected) => {
+                panic!("Unexpected result: {:?}, expected: {:?}", result, expected);
+            }
+        }
+        let rendered = diff.render_search_replace();
+        assert_eq!(crate::parse_search_replace(&rendered).unwrap(), diff);
+    }
+
+    // Any diff renders to blocks that parse back to the same changes.
+    let diff = crate::diff(input, "fn main() {\n    let x = 2;\n    old(x);\n}\n");
+    let blocks = crate::parse_search_replace(&diff.render_search_replace()).unwrap();
+    assert_eq!(
+        blocks.patch(input).unwrap(),
+        "fn main() {\n    let x = 2;\n    old(x);\n}\n"
+    );
+
+    // Lines a block ends with that the next block starts with stay context,
+    // where the next hunk is looked for.
+    let (a, b) = (
+        "d\nf\nb\na\n\nf\nb\ng\nf\nf",
+        "d\nf\nb\na\ny0\nf\nb\ng\nf\ny2",
+    );
+    let diff = crate::diff(a, b);
+    let blocks = crate::parse_search_replace(&diff.render_search_replace()).unwrap();
+    assert_eq!(blocks, diff);
+    assert_eq!(blocks.patch(a).unwrap(), b);
+
+    let errors = vec![
+        (
+            "<<<<<<< SEARCH\na\n>>>>>>> REPLACE\n",
+            "Line 3: Replace marker before divider",
+        ),
+        (
+            "<<<<<<< SEARCH\na\n=======\nb\n",
+            "Line 1: Search/replace block is not closed",
+        ),
+        (
+            "Nothing to change here.\n",
+            "No search/replace blocks found",
+        ),
+    ];
+    for (blocks, expected_msg) in errors {
+        match crate::parse_search_replace(blocks) {
+            Err(Error::Parse { details, .. }) => {
+                assert!(details.contains(expected_msg), "{details}")
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn test_feedback() {
+    let input = "fn main() {\n    let x = 1;\n    old(x);\n}\n\nfn other() {\n    old(x);\n}\n";
+    let test_cases = vec![
+        (
+            "@@ @@\n fn main() {\n     let x = 2;\n-    old(x);\n+    new(x);\n }\n",
+            "The patch could not be applied: 1 of 1 hunk failed.
+
+Hunk 1 failed: its context lines were not found in the file.
+@@ @@
+ fn main() {
+     let x = 2;
+-    old(x);
++    new(x);
+ }
+The closest lines in the file are 1-4:
+1 | fn main() {
+2 |     let x = 1;
+3 |     old(x);
+4 | }
+5 | 
+6 | fn other() {
+Retry with the hunk's unchanged and deleted lines copied exactly from the file:
+@@ @@
+ fn main() {
+     let x = 1;
+-    old(x);
++    new(x);
+ }
+",
+        ),
+        (
+            "@@ @@\n-    old(x);\n+    new(x);\n }\n",
+            "The patch could not be applied: 1 of 1 hunk failed.
+
+Hunk 1 failed: its context matches 2 places in the file.
+@@ @@
+-    old(x);
++    new(x);
+ }
+Match at lines 3-4:
+1 | fn main() {
+2 |     let x = 1;
+3 |     old(x);
+4 | }
+5 | 
+6 | fn other() {
+Match at lines 7-8:
+5 | 
+6 | fn other() {
+7 |     old(x);
+8 | }
+Add context lines from before or after the change so that the hunk matches only one place.
+",
+        ),
+        (
+            "@@ @@\n fn main() {\n@@ @@\n fn unrelated() {\n-    gone();\n",
+            "The patch could not be applied: 1 of 2 hunks failed.
+
+Hunk 2 failed: its context lines were not found in the file.
+@@ @@
+ fn unrelated() {
+-    gone();
+No similar lines were found in the file.
+",
+        ),
+    ];
+
+    for (diff_str, expected) in test_cases {
+        let diff = crate::parse(diff_str).unwrap();
+        let diagnostics = diff.check(input);
+        assert_eq!(diff.feedback(input, &diagnostics), expected);
+    }
+
+    let diff = crate::parse("@@ @@\n a\n-b\n+c\n").unwrap();
+    assert_eq!(diff.feedback("a\nb\n", &diff.check("a\nb\n")), "");
+}
+
+/// A small deterministic generator for property tests.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    /// Generates text from a few distinct lines, so that generated files
+    /// share lines and diffs have real context to match.
+    fn text(&mut self, newline: bool) -> String {
+        let len = self.next(8);
+        let mut text: Vec<String> = (0..len)
+            .map(|_| format!("line {}", self.next(12)))
+            .collect();
+        if newline && !text.is_empty() {
+            text.push(String::new());
+        }
+        text.join("\n")
+    }
+}
+
+#[test]
+fn test_invert_round_trip() {
+    let mut rng = Rng(0x5eed);
+    let (mut exact, mut ambiguous) = (0, 0);
+    for _ in 0..2000 {
+        let (old_newline, new_newline) = (rng.next(2) == 0, rng.next(2) == 0);
+        let old = rng.text(old_newline);
+        let new = rng.text(new_newline);
+        let diff = crate::diff(&old, &new);
+        assert_eq!(diff.invert().invert(), diff);
+        assert_eq!(
+            crate::parse(&diff.invert().render()).unwrap(),
+            diff.invert()
+        );
+        // `diff` keeps fixed context, which may not locate the hunks
+        // uniquely. Only diffs that apply forward are expected to revert.
+        let patched = match diff.patch(&old) {
+            Ok(patched) => patched,
+            Err(Error::AmbiguousMatch { .. }) => continue,
+            Err(e) => panic!("{old:?} -> {new:?}: {e:?}"),
+        };
+        assert!(patched.lines().eq(new.lines()), "{old:?} -> {new:?}");
+        // Reverting gives back the original's lines, with the trailing
+        // newline of the text being reverted.
+        let expected = match old.trim_end_matches('\n') {
+            "" => String::new(),
+            lines if new.ends_with('\n') => format!("{lines}\n"),
+            lines => lines.to_string(),
+        };
+        match diff.revert(&new) {
+            Ok(reverted) => {
+                assert_eq!(reverted, expected, "{old:?} -> {new:?}: {diff:?}");
+                exact += 1;
+            }
+            // Added lines can make the inverse's context ambiguous. Each
+            // reported candidate must be a real match of the hunk's anchor.
+            Err(Error::AmbiguousMatch { .. }) => {
+                let inverse = diff.invert();
+                let lines: Vec<&str> = new.lines().collect();
+                let diagnostics = inverse.check(&new);
+                assert!(!diagnostics.is_empty());
+                for d in &diagnostics {
+                    let DiagnosticKind::Ambiguous { candidates } = &d.kind else {
+                        panic!("{old:?} -> {new:?}: {diagnostics:?}");
+                    };
+                    let h = &inverse.hunks[d.hunk];
+                    let anchor: Vec<&String> = if h.context_before.is_empty() {
+                        h.deletions.iter().chain(&h.context_after).collect()
+                    } else {
+                        h.context_before.iter().collect()
+                    };
+                    assert!(candidates.len() > 1);
+                    for &c in candidates {
+                        assert!(
+                            anchor
+                                .iter()
+                                .enumerate()
+                                .all(|(j, l)| lines.get(c + j) == Some(&l.as_str())),
+                            "{old:?} -> {new:?}: {candidates:?}"
+                        );
+                    }
+                }
+                ambiguous += 1;
+            }
+            Err(e) => panic!("{old:?} -> {new:?}: {e:?}"),
+        }
+    }
+    assert!(
+        exact > 10 * ambiguous,
+        "{exact} exact, {ambiguous} ambiguous"
+    );
+}
+
+#[test]
+fn test_compose() {
+    let a = "fn main() {\n    let x = 1;\n    let y = 2;\n    println!(\"{}\", x);\n}\n\nfn other() {\n    let z = 3;\n}\n";
+    let test_cases = vec![
+        // The second diff edits a line the first one added.
+        (
+            "@@ @@\n fn main() {\n-    let x = 1;\n+    let x = 10;\n     let y = 2;\n",
+            "@@ @@\n-    let x = 10;\n+    let x = 100;\n     let y = 2;\n",
+            Ok(vec![Hunk {
+                context_before: vec!["fn main() {".to_string()],
+                deletions: vec!["    let x = 1;".to_string()],
+                additions: vec!["    let x = 100;".to_string()],
+                context_after: vec!["    let y = 2;".to_string()],
+            }]),
+        ),
+        // The second diff reaches past the first one's context.
+        (
+            "@@ @@\n fn main() {\n-    let x = 1;\n+    let x = 10;\n",
+            "@@ @@\n     let x = 10;\n     let y = 2;\n-    println!(\"{}\", x);\n+    println!(\"{}\", x + y);\n }\n",
+            Ok(vec![Hunk {
+                context_before: vec!["fn main() {".to_string()],
+                deletions: vec![
+                    "    let x = 1;".to_string(),
+                    "    let y = 2;".to_string(),
+                    "    println!(\"{}\", x);".to_string(),
+                ],
+                additions: vec![
+                    "    let x = 10;".to_string(),
+                    "    let y = 2;".to_string(),
+                    "    println!(\"{}\", x + y);".to_string(),
+                ],
+                context_after: vec!["}".to_string()],
+            }]),
+        ),
+        // The second diff joins two hunks of the first.
+        (
+            "@@ @@\n fn main() {\n-    let x = 1;\n+    let x = 10;\n@@ @@\n-    println!(\"{}\", x);\n+    println!(\"{}\", y);\n }\n",
+            "@@ @@\n     let x = 10;\n-    let y = 2;\n+    let y = 20;\n     println!(\"{}\", y);\n",
+            Ok(vec![Hunk {
+                context_before: vec!["fn main() {".to_string()],
+                deletions: vec![
+                    "    let x = 1;".to_string(),
+                    "    let y = 2;".to_string(),
+                    "    println!(\"{}\", x);".to_string(),
+                ],
+                additions: vec![
+                    "    let x = 10;".to_string(),
+                    "    let y = 20;".to_string(),
+                    "    println!(\"{}\", y);".to_string(),
+                ],
+                context_after: vec!["}".to_string()],
+            }]),
+        ),
+        // The second diff undoes the first.
+        (
+            "@@ @@\n     println!(\"{}\", x);\n+    let w = 4;\n }\n",
+            "@@ @@\n-    let w = 4;\n }\n",
+            Ok(vec![]),
+        ),
+        // Without the original, the order of unrelated hunks is unknown.
+        (
+            "@@ @@\n-    let x = 1;\n+    let x = 10;\n",
+            "@@ @@\n-    let z = 3;\n+    let z = 30;\n",
+            Err("order of the composed hunks is unknown"),
+        ),
+    ];
+
+    for (first, second, expected) in test_cases {
+        let (d1, d2) = (crate::parse(first).unwrap(), crate::parse(second).unwrap());
+        let c = d2.patch(&d1.patch(a).unwrap()).unwrap();
+        match (FuDiff::compose(&d1, &d2), expected) {
+            (Ok(composed), Ok(hunks)) => {
+                assert_eq!(composed.hunks, hunks);
+                assert_eq!(composed.patch(a).unwrap(), c);
+            }
+            (Err(Error::AmbiguousMatch { details, .. }), Err(expected_msg)) => {
+                assert!(details.contains(expected_msg), "{details}");
+            }
+            (result, expected) => {
+                panic!("Unexpected result: {:?}, expected: {:?}", result, expected);
+            }
+        }
+        // With the original, composition always succeeds.
+        let composed = FuDiff::compose_with(&d1, &d2, a).unwrap();
+        assert_eq!(composed.patch(a).unwrap(), c);
+    }
+
+    // A hunk of the second diff that spans a deletion of the first is
+    // folded into it.
+    let (a, b) = ("e\n\nc\nc\ne\nb\nh\na", "e\n\nc\ne\ny0\nh\na");
+    let c = format!("{b}\nxf");
+    let (d1, d2) = (crate::diff(a, b), crate::diff(b, &c));
+    assert_eq!(FuDiff::compose(&d1, &d2).unwrap().patch(a).unwrap(), c);
+
+    // Either side may be empty.
+    let d = crate::parse("@@ @@\n-    let z = 3;\n").unwrap();
+    let empty = FuDiff { hunks: vec![] };
+    assert_eq!(FuDiff::compose(&empty, &d).unwrap(), d);
+    assert_eq!(FuDiff::compose(&d, &empty).unwrap(), d);
+
+    // Hunks of the first diff may share context that the second changes.
+    let d1 = crate::parse("@@ @@\n-x\n a\n@@ @@\n a\n-b\n+B\n").unwrap();
+    let d2 = crate::parse("@@ @@\n+y\n a\n B\n").unwrap();
+    let composed = FuDiff::compose(&d1, &d2).unwrap();
+    assert_eq!(composed.patch("x\na\nb\n").unwrap(), "y\na\nB\n");
+    let composed = FuDiff::compose_with(&d1, &d2, "x\na\nb\n").unwrap();
+    assert_eq!(composed.patch("x\na\nb\n").unwrap(), "y\na\nB\n");
+}
+
+#[test]
+fn test_compose_round_trip() {
+    let mut rng = Rng(0xc0de);
+    let (mut composed, mut failed, mut refused) = (0, 0, 0);
+    for _ in 0..3000 {
+        let a = rng.text(false);
+        let b = rng.text(false);
+        let c = rng.text(false);
+        let (d1, d2) = (crate::diff(&a, &b), crate::diff(&b, &c));
+        if d1.patch(&a).ok().as_deref() != Some(b.as_str())
+            || d2.patch(&b).ok().as_deref() != Some(c.as_str())
+        {
+            continue;
+        }
+        // With the original, composition always succeeds.
+        let with =
+            FuDiff::compose_with(&d1, &d2, &a).unwrap_or_else(|e| panic!("{a:?} -> {c:?}: {e:?}"));
+        assert_eq!(with.patch(&a).unwrap(), c, "{a:?} -> {b:?} -> {c:?}");
+        match FuDiff::compose(&d1, &d2).map(|d| d.patch(&a)) {
+            Ok(Ok(patched)) => {
+                assert_eq!(patched, c, "{a:?} -> {b:?} -> {c:?}");
+                composed += 1;
+            }
+            Ok(Err(_)) => failed += 1,
+            Err(_) => refused += 1,
+        }
+    }
+    // A composed diff always applies and never produces the wrong text, but
+    // sometimes the order of unrelated hunks can't be told.
+    assert!(
+        composed > 1000 && failed == 0,
+        "{composed} composed, {failed} failed, {refused} refused"
+    );
+}
+
+#[test]
+fn test_rebase() {
+    let base = "fn a() {\n    one();\n}\n\nfn b() {\n    two();\n}\n";
+    let test_cases = vec![
+        // Upstream added lines above the change.
+        (
+            "@@ @@\n fn b() {\n-    two();\n+    three();\n }\n",
+            "use x;\n\nfn a() {\n    one();\n}\n\nfn b() {\n    two();\n}\n",
+            Ok("use x;\n\nfn a() {\n    one();\n}\n\nfn b() {\n    three();\n}\n"),
+        ),
+        // Upstream changed the hunk's context.
+        (
+            "@@ @@\n fn b() {\n-    two();\n+    three();\n }\n",
+            "fn a() {\n    one();\n}\n\nfn b(x: u8) {\n    two();\n}\n",
+            Ok("fn a() {\n    one();\n}\n\nfn b(x: u8) {\n    three();\n}\n"),
+        ),
+        // A copy of the hunk's context upstream widens the rebased context.
+        (
+            "@@ @@\n fn b() {\n-    two();\n",
+            "fn a() {\n    one();\n}\n\nfn b() {\n    two();\n}\n\nfn b() {\n    two();\n    four();\n}\n",
+            Ok("fn a() {\n    one();\n}\n\nfn b() {\n}\n\nfn b() {\n    two();\n    four();\n}\n"),
+        ),
+        // Upstream changed a deleted line.
+        (
+            "@@ @@\n fn b() {\n-    two();\n+    three();\n }\n",
+            "fn a() {\n    one();\n}\n\nfn b() {\n    deux();\n}\n",
+            Err(vec![(0, 5..6, 5..6)]),
+        ),
+        // Upstream removed the lines around an insertion.
+        (
+            "@@ @@\n fn a() {\n+    zero();\n     one();\n",
+            "fn b() {\n    two();\n}\n",
+            Err(vec![(0, 1..1, 0..4)]),
+        ),
+    ];
+
+    for (diff_str, base_new, expected) in test_cases {
+        let diff = crate::parse(diff_str).unwrap();
+        match (diff.rebase(base, base_new), expected) {
+            (Ok(rebased), Ok(expected)) => {
+                assert_eq!(rebased.patch(base_new).unwrap(), expected);
+            }
+            (Err(Error::Conflict { conflicts, .. }), Err(expected)) => {
+                let found: Vec<_> = conflicts
+                    .into_iter()
+                    .map(|c| (c.hunk, c.ours, c.theirs))
+                    .collect();
+                assert_eq!(found, expected);
+            }
+            (result, expected) => {
+                panic!("Unexpected result: {:?}, expected: {:?}", result, expected);
+            }
+        }
+    }
+
+    // Context doesn't reach back past the previous hunk's deletions when
+    // upstream removes lines between them.
+    let old = "e\nx\n\nd\nxf";
+    let diff = crate::diff(old, "e\nxe\nx\n\nd\nxf");
+    let rebased = diff.rebase(old, "e\nx\nd\nxf").unwrap();
+    assert_eq!(rebased.patch("e\nx\nd\nxf").unwrap(), "e\nxe\nx\nd\nxf");
+
+    // A hunk whose context can't be widened any further takes the lines
+    // before its change into it.
+    let diff = crate::parse("@@ @@\n y3\n-a\n+A\n").unwrap();
+    let rebased = diff.rebase("y3\na\nb\ny0\n", "y0\na\nb\ny0\n").unwrap();
+    assert_eq!(rebased.patch("y0\na\nb\ny0\n").unwrap(), "y0\nA\nb\ny0\n");
+
+    // A diff that doesn't apply to the old base can't be rebased.
+    let diff = crate::parse("@@ @@\n-missing\n").unwrap();
+    assert!(matches!(diff.rebase(base, base), Err(Error::Apply { .. })));
+}
+
+#[test]
+fn test_merge3() {
+    let base = "a\nb\nc\nd\ne\n";
+    let test_cases = vec![
+        // Changes to different lines combine.
+        (
+            "a\nB\nc\nd\ne\n",
+            "a\nb\nc\nD\ne\n",
+            "a\nB\nc\nD\ne\n",
+            vec![],
+        ),
+        // Identical changes are taken once.
+        (
+            "a\nB\nc\nd\ne\n",
+            "a\nB\nc\nd\ne\n",
+            "a\nB\nc\nd\ne\n",
+            vec![],
+        ),
+        // Insertions and deletions on either side.
+        (
+            "x\na\nb\nc\nd\ne\n",
+            "a\nb\nd\ne\n",
+            "x\na\nb\nd\ne\n",
+            vec![],
+        ),
+        // Differing changes to the same line conflict.
+        (
+            "a\nB\nc\nd\ne\n",
+            "a\nX\nc\nd\ne\n",
+            "a\n<<<<<<< ours\nB\n=======\nX\n>>>>>>> theirs\nc\nd\ne\n",
+            vec![ConflictRegion {
+                base: 1..2,
+                ours: 1..2,
+                theirs: 1..2,
+                output: 1..6,
+            }],
+        ),
+        // One side deletes what the other changes.
+        (
+            "a\nd\ne\n",
+            "a\nb\nC\nd\ne\n",
+            "a\n<<<<<<< ours\n=======\nb\nC\n>>>>>>> theirs\nd\ne\n",
+            vec![ConflictRegion {
+                base: 1..3,
+                ours: 1..1,
+                theirs: 1..3,
+                output: 1..6,
+            }],
+        ),
+        // Trailing newline changes are taken from the side that made them.
+        ("a\nb\nc\nd\ne", "a\nB\nc\nd\ne\n", "a\nB\nc\nd\ne", vec![]),
+    ];
+
+    for (ours, theirs, output, conflicts) in test_cases {
+        let merged = crate::merge3(base, ours, theirs);
+        assert_eq!(merged.output, output, "{ours:?} + {theirs:?}");
+        assert_eq!(merged.conflicts, conflicts);
+    }
+
+    // Merging with an unchanged side gives the other side.
+    for text in ["", "a\n", "x\ny", "a\nb\nc\nd\ne\nf\n"] {
+        assert_eq!(crate::merge3(base, base, text).output, text);
+        assert_eq!(crate::merge3(base, text, base).output, text);
+    }
+}
+
+#[test]
+fn test_conflicts_with() {
+    let base = "a\nb\nc\nd\ne\nf\ng\n";
+    let test_cases = vec![
+        // Far apart changes apply together.
+        ("@@ @@\n a\n-b\n+B\n c\n", "@@ @@\n e\n-f\n+F\n g\n", vec![]),
+        // The same line changed twice.
+        (
+            "@@ @@\n a\n-b\n+B\n c\n",
+            "@@ @@\n a\n-b\n+X\n c\n",
+            vec![(0, 0, 1..2, 1..2)],
+        ),
+        // Adjacent changes without context.
+        (
+            "@@ @@\n-b\n+B\n",
+            "@@ @@\n-c\n+C\n",
+            vec![(0, 0, 1..2, 2..3)],
+        ),
+        // Insertions at the same point.
+        (
+            "@@ @@\n c\n+x\n d\n",
+            "@@ @@\n c\n+y\n d\n",
+            vec![(0, 0, 3..3, 3..3)],
+        ),
+        // One hunk changes the other's context.
+        (
+            "@@ @@\n a\n-b\n+B\n c\n d\n",
+            "@@ @@\n-d\n+D\n e\n",
+            vec![(0, 0, 1..2, 3..4)],
+        ),
+        // Only the conflicting pair of several hunks is reported.
+        (
+            "@@ @@\n a\n-b\n+B\n@@ @@\n f\n-g\n+G\n",
+            "@@ @@\n-g\n",
+            vec![(1, 0, 6..7, 6..7)],
+        ),
+        // A context-only hunk conflicts with changes to its context.
+        (
+            "@@ @@\n a\n b\n",
+            "@@ @@\n a\n-b\n+B\n",
+            vec![(0, 0, 0..2, 1..2)],
+        ),
+        // But not with changes next to it, nor with other context-only hunks.
+        ("@@ @@\n a\n b\n", "@@ @@\n b\n-c\n+C\n", vec![]),
+        ("@@ @@\n a\n b\n", "@@ @@\n b\n c\n", vec![]),
+    ];
+
+    for (ours, theirs, expected) in test_cases {
+        let (ours, theirs) = (crate::parse(ours).unwrap(), crate::parse(theirs).unwrap());
+        let found: Vec<_> = ours
+            .conflicts_with(&theirs, base)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.hunk, c.other.unwrap(), c.ours, c.theirs))
+            .collect();
+        assert_eq!(found, expected);
+        // Conflicts are symmetric.
+        assert_eq!(
+            theirs.conflicts_with(&ours, base).unwrap().len(),
+            expected.len()
+        );
+    }
+
+    // Hunks that can't be located are reported, naming the diff.
+    let good = crate::parse("@@ @@\n a\n-b\n+B\n").unwrap();
+    let bad = crate::parse("@@ @@\n a\n-b\n+B\n@@ @@\n-z\n").unwrap();
+    for (ours, theirs, name) in [(&bad, &good, "This diff"), (&good, &bad, "The other diff")] {
+        match ours.conflicts_with(theirs, base) {
+            Err(Error::Apply { user, .. }) => assert!(user.starts_with(name), "{user}"),
+            other => panic!("expected an apply error, got {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_interdiff() {
+    let base = "a\nb\nc\nd\ne\n";
+    let test_cases = vec![
+        // Identical revisions differ in nothing.
+        ("@@ @@\n a\n-b\n+B\n c\n", "@@ @@\n a\n-b\n+B\n c\n", ""),
+        // The second revision changes the replacement.
+        (
+            "@@ @@\n a\n-b\n+B\n c\n",
+            "@@ @@\n a\n-b\n+X\n c\n",
+            "@@ @@\n a\n-B\n+X\n c\n d\n e",
+        ),
+        // The second revision adds a hunk and drops none.
+        (
+            "@@ @@\n a\n-b\n+B\n c\n",
+            "@@ @@\n a\n-b\n+B\n c\n@@ @@\n d\n-e\n+E\n",
+            "@@ @@\n B\n c\n d\n-e\n+E\n",
+        ),
+    ];
+
+    for (d1, d2, expected) in test_cases {
+        let (d1, d2) = (crate::parse(d1).unwrap(), crate::parse(d2).unwrap());
+        let delta = crate::interdiff(base, &d1, &d2).unwrap();
+        assert_eq!(delta.render(), expected);
+        assert_eq!(
+            delta.patch(&d1.patch(base).unwrap()).unwrap(),
+            d2.patch(base).unwrap()
+        );
+    }
+
+    let good = crate::parse("@@ @@\n a\n-b\n+B\n").unwrap();
+    let bad = crate::parse("@@ @@\n x\n-y\n").unwrap();
+    for (d1, d2, n) in [(&bad, &good, 1), (&good, &bad, 2)] {
+        match crate::interdiff(base, d1, d2) {
+            Err(Error::Apply { user, .. }) => {
+                assert!(user.starts_with(&format!("Revision {n} ")), "{user}")
+            }
+            other => panic!("expected an apply error, got {other:?}"),
+        }
+    }
+
+    // The interdiff takes the first revision's output to the second's.
+    let mut rng = Rng(0x1d1f);
+    let mut checked = 0;
+    for _ in 0..2000 {
+        let base = rng.text(false);
+        let (out1, out2) = (rng.text(false), rng.text(false));
+        let (d1, d2) = (crate::diff(&base, &out1), crate::diff(&base, &out2));
+        let (Ok(first), Ok(second)) = (d1.patch(&base), d2.patch(&base)) else {
+            continue;
+        };
+        let delta = crate::interdiff(&base, &d1, &d2).unwrap();
+        assert_eq!(
+            delta.patch(&first).unwrap(),
+            second,
+            "{base:?}: {d1:?} {d2:?}"
+        );
+        checked += 1;
+    }
+    assert!(checked > 1000, "{checked} checked");
+}
+
+#[test]
+#[allow(clippy::single_range_in_vec_init)]
+fn test_intraline() {
+    use crate::{intraline, Granularity};
+
+    let test_cases = vec![
+        // Identical lines.
+        ("same", "same", Granularity::Word, vec![], vec![]),
+        // A replaced word.
+        (
+            "let x = 1;",
+            "let y = 1;",
+            Granularity::Word,
+            vec![4..5],
+            vec![4..5],
+        ),
+        // Whole words are reported even for a one-character change.
+        (
+            "count += 1",
+            "counts += 1",
+            Granularity::Word,
+            vec![0..5],
+            vec![0..6],
+        ),
+        (
+            "count += 1",
+            "counts += 1",
+            Granularity::Char,
+            vec![],
+            vec![5..6],
+        ),
+        // Inserted and removed words.
+        ("a b", "a new b", Granularity::Word, vec![], vec![2..6]),
+        ("a old b", "a b", Granularity::Word, vec![2..6], vec![]),
+        // Punctuation is compared one character at a time.
+        (
+            "f(a, b)",
+            "f(a; b)",
+            Granularity::Word,
+            vec![3..4],
+            vec![3..4],
+        ),
+        // Ranges are byte offsets that keep characters whole.
+        ("héllo", "hallo", Granularity::Char, vec![1..3], vec![1..2]),
+        ("", "new", Granularity::Char, vec![], vec![0..3]),
+    ];
+
+    for (old, new, granularity, expected_old, expected_new) in test_cases {
+        let spans = intraline(old, new, granularity);
+        assert_eq!(spans.old, expected_old, "{old:?} -> {new:?}");
+        assert_eq!(spans.new, expected_new, "{old:?} -> {new:?}");
+    }
+
+    // A one-character change in a long line is found precisely.
+    let old = "x".repeat(100) + "a" + &"y".repeat(100);
+    let new = "x".repeat(100) + "b" + &"y".repeat(100);
+    let spans = intraline(&old, &new, Granularity::Char);
+    assert_eq!((spans.old, spans.new), (vec![100..101], vec![100..101]));
+
+    // Hunks pair deletions with additions in order.
+    let diff = crate::parse("@@ @@\n-one two\n-three\n+one 2\n").unwrap();
+    let spans = diff.hunks[0].intraline(Granularity::Word);
+    assert_eq!(spans.len(), 1);
+    assert_eq!((&spans[0].old, &spans[0].new), (&vec![4..7], &vec![4..5]));
+}
+
+#[cfg(feature = "color")]
+#[test]
+fn test_render_color() {
+    use crate::{ColorMode, ColorOptions, Granularity};
+
+    let diff = crate::parse("@@ @@\n a b\n-let x = 1;\n+let y = 1;\n+\tnew\n").unwrap();
+    let test_cases = vec![
+        // Without color the layout matches `render`, ending with a newline.
+        (
+            ColorOptions {
+                mode: ColorMode::None,
+                ..Default::default()
+            },
+            "@@ @@\n a b\n-let x = 1;\n+let y = 1;\n+\tnew\n",
+        ),
+        (
+            ColorOptions {
+                mode: ColorMode::None,
+                whitespace: true,
+                ..Default::default()
+            },
+            "@@ @@\n a·b\n-let·x·=·1;\n+let·y·=·1;\n+→new\n",
+        ),
+        (
+            ColorOptions {
+                highlight: None,
+                ..Default::default()
+            },
+            "\x1b[36m@@ @@\x1b[0m\n\
+             \x1b[2m a b\x1b[0m\n\
+             \x1b[31m-let x = 1;\x1b[0m\n\
+             \x1b[32m+let y = 1;\x1b[0m\n\
+             \x1b[32m+\tnew\x1b[0m\n",
+        ),
+        (
+            ColorOptions::default(),
+            "\x1b[36m@@ @@\x1b[0m\n\
+             \x1b[2m a b\x1b[0m\n\
+             \x1b[31m-let \x1b[7mx\x1b[0m\x1b[31m = 1;\x1b[0m\n\
+             \x1b[32m+let \x1b[7my\x1b[0m\x1b[32m = 1;\x1b[0m\n\
+             \x1b[32m+\tnew\x1b[0m\n",
+        ),
+        (
+            ColorOptions {
+                mode: ColorMode::Ansi256,
+                highlight: Some(Granularity::Char),
+                ..Default::default()
+            },
+            "\x1b[38;5;75m@@ @@\x1b[0m\n\
+             \x1b[38;5;245m a b\x1b[0m\n\
+             \x1b[38;5;203m-let \x1b[48;5;52mx\x1b[0m\x1b[38;5;203m = 1;\x1b[0m\n\
+             \x1b[38;5;114m+let \x1b[48;5;22my\x1b[0m\x1b[38;5;114m = 1;\x1b[0m\n\
+             \x1b[38;5;114m+\tnew\x1b[0m\n",
+        ),
+    ];
+
+    for (opts, expected) in test_cases {
+        assert_eq!(diff.render_color(&opts), expected);
+    }
+
+    // Control characters in the diff can't drive the terminal.
+    let diff = crate::parse("@@ @@\n-\x1b[2Jx\x07\x0b\n+y\u{9b}31m\x7f\n").unwrap();
+    assert_eq!(
+        diff.render_color(&ColorOptions {
+            highlight: None,
+            ..Default::default()
+        }),
+        "\x1b[36m@@ @@\x1b[0m\n\
+         \x1b[31m-␛[2Jx␇␋\x1b[0m\n\
+         \x1b[32m+y\\u{9b}31m␡\x1b[0m\n"
+    );
+}
+
+#[test]
+fn test_render_html() {
+    use crate::{HtmlOptions, Layout};
+
+    let base = "one\ntwo\nthree\nfour\nfive\nsix\n";
+    let diff = crate::parse("@@ @@\n two\n-three\n+3 <three>\n+extra\n four\n").unwrap();
+    let body = |html: String| {
+        let start = html.find("</style>\n").unwrap() + "</style>\n".len();
+        html[start..].to_string()
+    };
+
+    let unified = body(diff.render_html(base, &HtmlOptions::default()).unwrap());
+    assert_eq!(
+        unified,
+        "<details><summary>1 unchanged line</summary>\n\
+         <table>\n\
+         <tr><td class=\"num\">1</td><td class=\"num\">1</td><td class=\"mark\"> </td><td>one</td></tr>\n\
+         </table>\n\
+         </details>\n\
+         <table>\n\
+         <tr><td class=\"num\">2</td><td class=\"num\">2</td><td class=\"mark\"> </td><td>two</td></tr>\n\
+         <tr class=\"del\"><td class=\"num\">3</td><td class=\"num\"></td><td class=\"mark\">-</td><td>three</td></tr>\n\
+         <tr class=\"add\"><td class=\"num\"></td><td class=\"num\">3</td><td class=\"mark\">+</td><td><mark>3 &lt;</mark>three<mark>&gt;</mark></td></tr>\n\
+         <tr class=\"add\"><td class=\"num\"></td><td class=\"num\">4</td><td class=\"mark\">+</td><td>extra</td></tr>\n\
+         <tr><td class=\"num\">4</td><td class=\"num\">5</td><td class=\"mark\"> </td><td>four</td></tr>\n\
+         </table>\n\
+         <details><summary>2 unchanged lines</summary>\n\
+         <table>\n\
+         <tr><td class=\"num\">5</td><td class=\"num\">6</td><td class=\"mark\"> </td><td>five</td></tr>\n\
+         <tr><td class=\"num\">6</td><td class=\"num\">7</td><td class=\"mark\"> </td><td>six</td></tr>\n\
+         </table>\n\
+         </details>\n\
+         </div>\n"
+    );
+
+    let opts = HtmlOptions {
+        layout: Layout::SideBySide,
+        highlight: None,
+    };
+    let side = body(diff.render_html(base, &opts).unwrap());
+    assert!(side.contains(
+        "<tr><td class=\"num\">3</td><td class=\"del\">three</td>\
+         <td class=\"num\">3</td><td class=\"add\">3 &lt;three&gt;</td></tr>\n\
+         <tr><td class=\"num empty\"></td><td class=\"empty\"></td>\
+         <td class=\"num\">4</td><td class=\"add\">extra</td></tr>\n"
+    ));
+    assert!(side.contains(
+        "<tr><td class=\"num\">6</td><td>six</td><td class=\"num\">7</td><td>six</td></tr>\n"
+    ));
+
+    // Hunks that share context are laid out once, in order.
+    let diff = crate::parse("@@ @@\n one\n-two\n+2\n three\n@@ @@\n three\n-four\n+4\n").unwrap();
+    let html = diff.render_html(base, &HtmlOptions::default()).unwrap();
+    assert_eq!(html.matches(">three<").count(), 1);
+    assert_eq!(html.matches("<details>").count(), 1);
+
+    assert!(matches!(
+        crate::parse("@@ @@\n-missing\n")
+            .unwrap()
+            .render_html(base, &HtmlOptions::default()),
+        Err(Error::Apply { .. })
+    ));
+}
+
+#[test]
+fn test_render_side_by_side() {
+    use crate::SideBySideOptions;
+
+    let base = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n";
+    let opts = |width, context| SideBySideOptions {
+        width,
+        context,
+        ..Default::default()
+    };
+    let test_cases = vec![
+        // Replaced, added and unchanged lines, with distant context elided.
+        (
+            base,
+            "@@ @@\n two\n-three\n+THREE\n+new\n four\n",
+            opts(31, 1),
+            "1 one            1 one\n\
+             2 two            2 two\n\
+             3 three        | 3 THREE\n\
+             \x20              > 4 new\n\
+             4 four           5 four\n\
+             5 five           6 five\n\
+             \x20    ⋯ 3 unchanged lines ⋯\n",
+        ),
+        // Long lines wrap, and deleted lines leave the right side blank.
+        (
+            "abcdefghijklmnop\nkeep\n",
+            "@@ @@\n-abcdefghijklmnop\n keep\n",
+            opts(27, 3),
+            "1 abcdefghij <\n\
+             \x20 klmnop\n\
+             2 keep         1 keep\n",
+        ),
+        // Wide characters take two columns and aren't split.
+        (
+            "x\n",
+            "@@ @@\n-x\n+日本語のテキスト\n",
+            opts(27, 3),
+            "1 x          | 1 日本語のテ\n\
+             \x20                キスト\n",
+        ),
+        // Tabs expand to the tab width.
+        (
+            "\tx\n",
+            "@@ @@\n-\tx\n+y\n",
+            opts(27, 3),
+            "1     x      | 1 y\n",
+        ),
+        // Control characters are escaped, and take the columns of their
+        // escapes.
+        (
+            "\x1b[2J\n",
+            "@@ @@\n-\x1b[2J\n+\u{9b}2J\n",
+            opts(27, 3),
+            "1 ␛[2J       | 1 \\u{9b}2J\n",
+        ),
+    ];
+
+    for (base, diff, opts, expected) in test_cases {
+        let diff = crate::parse(diff).unwrap();
+        assert_eq!(diff.render_side_by_side(base, &opts).unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_stats() {
+    use crate::{diffstat, Stats};
+
+    let test_cases = vec![
+        ("", Stats::default()),
+        (
+            "@@ @@\n a\n-b\n+B\n+C\n c\n",
+            Stats {
+                hunks: 1,
+                added: 2,
+                removed: 1,
+                context: 2,
+            },
+        ),
+        (
+            "@@ @@\n a\n ...\n-b\n@@ @@\n+x\n",
+            Stats {
+                hunks: 2,
+                added: 1,
+                removed: 1,
+                context: 2,
+            },
+        ),
+    ];
+    for (input, expected) in test_cases {
+        let diff = if input.is_empty() {
+            FuDiff::default()
+        } else {
+            crate::parse(input).unwrap()
+        };
+        assert_eq!(diff.stats(), expected, "{input}");
+    }
+
+    // A literal "..." is context, but a parsed elision marker is not.
+    let opts = crate::ParseOptions {
+        elisions: vec!["...".to_string()],
+        ..Default::default()
+    };
+    let elided = crate::parse_with("@@ @@\n a\n ...\n-b\n@@ @@\n+x\n", &opts).unwrap();
+    assert_eq!(elided.stats().context, 1);
+
+    let small = crate::parse("@@ @@\n a\n-b\n+B\n+C\n").unwrap();
+    let large = crate::parse(&format!("@@ @@\n{}", "+x\n".repeat(40))).unwrap();
+    let empty = FuDiff::default();
+    assert_eq!(
+        diffstat(
+            &[("src/lib.rs", &small), ("README.md", &large), ("x", &empty)],
+            40
+        ),
+        " src/lib.rs |  3 +-\n README.md  | 40 +++++++++++++++++++++++\n x          |  0\n \
+         3 files changed, 42 insertions(+), 1 deletion(-)\n"
+    );
+    assert_eq!(
+        diffstat(&[("a", &small.invert())], 80),
+        " a | 3 +--\n 1 file changed, 1 insertion(+), 2 deletions(-)\n"
+    );
+}
+
+#[test]
+fn test_text_edits() {
+    use crate::{Position, PositionEncoding, TextEdit, TextRange};
+
+    let edit = |(l1, c1), (l2, c2), text: &str| TextEdit {
+        range: TextRange {
+            start: Position {
+                line: l1,
+                character: c1,
+            },
+            end: Position {
+                line: l2,
+                character: c2,
+            },
+        },
+        new_text: text.to_string(),
+    };
+    let test_cases = vec![
+        // Whole lines are replaced.
+        (
+            "a\nb\nc\n",
+            "@@ @@\n a\n-b\n+B\n+B2\n c\n",
+            PositionEncoding::Utf16,
+            vec![edit((1, 0), (2, 0), "B\nB2\n")],
+        ),
+        // Insertions are empty ranges, and hunks are edited separately.
+        (
+            "a\nb\nc\n",
+            "@@ @@\n a\n+x\n b\n@@ @@\n-c\n",
+            PositionEncoding::Utf16,
+            vec![edit((1, 0), (1, 0), "x\n"), edit((2, 0), (3, 0), "")],
+        ),
+        // An unterminated last line stays unterminated.
+        (
+            "a\nb",
+            "@@ @@\n a\n-b\n+B\n",
+            PositionEncoding::Utf16,
+            vec![edit((1, 0), (1, 1), "B")],
+        ),
+        (
+            "a\nb",
+            "@@ @@\n a\n-b\n",
+            PositionEncoding::Utf16,
+            vec![edit((0, 1), (1, 1), "")],
+        ),
+        (
+            "a\nb",
+            "@@ @@\n b\n+c\n",
+            PositionEncoding::Utf16,
+            vec![edit((1, 1), (1, 1), "\nc")],
+        ),
+        // CRLF line endings are kept and used for added lines.
+        (
+            "a\r\nb\r\n",
+            "@@ @@\n-a\n+A\n",
+            PositionEncoding::Utf16,
+            vec![edit((0, 0), (1, 0), "A\r\n")],
+        ),
+        // Adjacent hunks become one edit.
+        (
+            "a\nb\nc",
+            "@@ @@\n a\n-b\n@@ @@\n-c\n",
+            PositionEncoding::Utf16,
+            vec![edit((0, 1), (2, 1), "")],
+        ),
+        // Empty input gains the added lines without a trailing newline.
+        (
+            "",
+            "@@ @@\n+a\n+b\n",
+            PositionEncoding::Utf16,
+            vec![edit((0, 0), (0, 0), "a\nb")],
+        ),
+        // Characters count in the chosen code units.
+        (
+            "😀é\nb",
+            "@@ @@\n 😀é\n-b\n",
+            PositionEncoding::Utf16,
+            vec![edit((0, 3), (1, 1), "")],
+        ),
+        (
+            "😀é\nb",
+            "@@ @@\n 😀é\n-b\n",
+            PositionEncoding::Utf8,
+            vec![edit((0, 6), (1, 1), "")],
+        ),
+    ];
+
+    for (input, diff, encoding, expected) in test_cases {
+        let diff = crate::parse(diff).unwrap();
+        assert_eq!(
+            diff.text_edits(input, encoding).unwrap(),
+            expected,
+            "{input:?}"
+        );
+    }
+
+    assert!(matches!(
+        crate::parse("@@ @@\n-z\n")
+            .unwrap()
+            .text_edits("a\n", PositionEncoding::Utf16),
+        Err(Error::Apply { .. })
+    ));
+}
+
+#[test]
+fn test_to_edits() {
+    use crate::{apply_edits, Edit};
+
+    let diff = crate::parse("@@ @@\n a\n-b\n+B\n c\n@@ @@\n+x\n d\n").unwrap();
+    let edits = diff.to_edits("a\nb\nc\nd\n").unwrap();
+    assert_eq!(
+        edits,
+        vec![
+            Edit {
+                byte_range: 2..4,
+                replacement: "B\n".to_string(),
+            },
+            Edit {
+                byte_range: 6..6,
+                replacement: "x\n".to_string(),
+            },
+        ]
+    );
+    let mut text = "a\nb\nc\nd\n".to_string();
+    apply_edits(&mut text, &edits);
+    assert_eq!(text, "a\nB\nc\nx\nd\n");
+
+    // Applying the edits gives the same text as patching, keeping the
+    // input's trailing newline.
+    let mut rng = Rng(0xed17);
+    for _ in 0..2000 {
+        let newline = (rng.next(2) == 0, rng.next(2) == 0);
+        let old = rng.text(newline.0);
+        let new = rng.text(newline.1);
+        let diff = crate::diff(&old, &new);
+        // Reverting the inverse applies the diff keeping the trailing newline.
+        let expected = diff.invert().revert(&old);
+        match (diff.to_edits(&old), expected) {
+            (Ok(edits), Ok(expected)) => {
+                let mut text = old.clone();
+                apply_edits(&mut text, &edits);
+                assert_eq!(text, expected, "{old:?} -> {new:?}: {edits:?}");
+            }
+            (Err(_), Err(_)) => {}
+            (edits, expected) => panic!("{old:?}: {edits:?} vs {expected:?}"),
+        }
+    }
+}
+
+#[test]
+fn test_line_map() {
+    let test_cases = vec![
+        // No changes.
+        ("a\nb\n", "", vec![Some(0), Some(1)], vec![Some(0), Some(1)]),
+        // A replaced line, with a line added.
+        (
+            "a\nb\nc\n",
+            "@@ @@\n a\n-b\n+B\n+B2\n c\n",
+            vec![Some(0), None, Some(3)],
+            vec![Some(0), None, None, Some(2)],
+        ),
+        // Several hunks shift the lines after them.
+        (
+            "a\nb\nc\nd\ne\n",
+            "@@ @@\n-a\n b\n@@ @@\n d\n+x\n+y\n e\n",
+            vec![None, Some(0), Some(1), Some(2), Some(5)],
+            vec![Some(1), Some(2), Some(3), None, None, Some(4)],
+        ),
+        // Everything deleted.
+        ("a\nb\n", "@@ @@\n-a\n-b\n", vec![None, None], vec![]),
+    ];
+
+    for (input, diff, old_to_new, new_to_old) in test_cases {
+        let diff = if diff.is_empty() {
+            FuDiff::default()
+        } else {
+            crate::parse(diff).unwrap()
+        };
+        let map = diff.line_map(input).unwrap();
+        assert_eq!(map.old_to_new, old_to_new, "{input:?}");
+        assert_eq!(map.new_to_old, new_to_old, "{input:?}");
+        // The map agrees with the patched text.
+        let (old, new): (Vec<&str>, String) = (input.lines().collect(), diff.patch(input).unwrap());
+        let new: Vec<&str> = new.lines().collect();
+        assert_eq!(map.new_to_old.len(), new.len());
+        for (i, line) in new.iter().enumerate() {
+            if let Some(o) = map.old_line(i) {
+                assert_eq!(old[o], *line);
+                assert_eq!(map.new_line(o), Some(i));
+            }
+        }
+    }
+
+    let map = crate::parse("@@ @@\n-a\n")
+        .unwrap()
+        .line_map("a\nb\n")
+        .unwrap();
+    assert_eq!(
+        (map.new_line(1), map.new_line(0), map.new_line(9)),
+        (Some(0), None, None)
+    );
+    assert_eq!((map.old_line(0), map.old_line(1)), (Some(1), None));
+    assert!(crate::parse("@@ @@\n-z\n")
+        .unwrap()
+        .line_map("a\n")
+        .is_err());
+}
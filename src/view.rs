@@ -0,0 +1,115 @@
+//! Laying out a diff against the text it applies to, for renderers that show
+//! the whole text with line numbers.
+
+use std::ops::Range;
+
+use crate::{FuDiff, Granularity, PatchOptions, Result};
+
+/// One line of the old or new text.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Side {
+    /// Zero-based line number.
+    pub line: usize,
+    pub text: String,
+    /// Byte ranges of `text` changed within the line.
+    pub changed: Vec<Range<usize>>,
+}
+
+/// A row of the view: an unchanged line on both sides, or a changed line on
+/// either or both sides. A deleted line and the added line paired with it
+/// share a row.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Row {
+    pub old: Option<Side>,
+    pub new: Option<Side>,
+    pub changed: bool,
+}
+
+/// A run of rows.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum Block {
+    /// Lines no hunk touches, not even as context.
+    Unchanged(Vec<Row>),
+    /// A hunk with its context.
+    Hunk(Vec<Row>),
+}
+
+/// Locates `diff` in `base` and lays out every line of `base` and of the
+/// patched text as blocks, with the changed parts of paired lines compared in
+/// units of `highlight`.
+pub(crate) fn view(
+    diff: &FuDiff,
+    base: &str,
+    highlight: Option<Granularity>,
+) -> Result<Vec<Block>> {
+    let lines: Vec<&str> = base.lines().collect();
+    let located = diff.locate(&lines, &PatchOptions::default())?;
+
+    let same = |old: usize, new: usize| Row {
+        old: Some(Side {
+            line: old,
+            text: lines[old].to_string(),
+            changed: Vec::new(),
+        }),
+        new: Some(Side {
+            line: new,
+            text: lines[old].to_string(),
+            changed: Vec::new(),
+        }),
+        changed: false,
+    };
+
+    let mut blocks = Vec::new();
+    // The next old line to show, and how far the new line numbers have moved
+    // from the old ones.
+    let (mut pos, mut shift) = (0, 0isize);
+    let new_line = |old: usize, shift: isize| (old as isize + shift) as usize;
+    for loc in &located.locations {
+        let start = loc.start.max(pos);
+        if pos < start {
+            blocks.push(Block::Unchanged(
+                (pos..start).map(|i| same(i, new_line(i, shift))).collect(),
+            ));
+        }
+
+        let hunk = &diff.hunks[loc.hunk];
+        let mut rows: Vec<Row> = (start..loc.del_start)
+            .map(|i| same(i, new_line(i, shift)))
+            .collect();
+        let added = loc.additions(hunk, &lines);
+        let new_start = new_line(loc.del_start, shift);
+        for k in 0..(loc.del_end - loc.del_start).max(added.len()) {
+            let old = (loc.del_start + k < loc.del_end).then(|| lines[loc.del_start + k]);
+            let new = added.get(k).map(|l| l.as_ref());
+            let spans = match (old, new, highlight) {
+                (Some(o), Some(n), Some(g)) => crate::intraline(o, n, g),
+                _ => Default::default(),
+            };
+            rows.push(Row {
+                old: old.map(|text| Side {
+                    line: loc.del_start + k,
+                    text: text.to_string(),
+                    changed: spans.old,
+                }),
+                new: new.map(|text| Side {
+                    line: new_start + k,
+                    text: text.to_string(),
+                    changed: spans.new,
+                }),
+                changed: true,
+            });
+        }
+        shift += added.len() as isize - (loc.del_end - loc.del_start) as isize;
+        rows.extend((loc.del_end..loc.end).map(|i| same(i, new_line(i, shift))));
+        blocks.push(Block::Hunk(rows));
+        pos = loc.end.max(loc.del_end);
+    }
+    if pos < lines.len() {
+        blocks.push(Block::Unchanged(
+            (pos..lines.len())
+                .map(|i| same(i, new_line(i, shift)))
+                .collect(),
+        ));
+    }
+    Ok(blocks)
+}